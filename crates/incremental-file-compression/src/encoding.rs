@@ -0,0 +1,65 @@
+use std::io::{Read, Write};
+
+use anyhow::{anyhow, Result};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+/// Formats blocks can be compressed with, named as in HTTP's `Content-Encoding`. Compression
+/// only ever applies to the transfer or storage of a block, its hash is always over the raw data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Zstd,
+    Gzip,
+}
+
+impl Encoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+        }
+    }
+    /// Reads a name as found in `Content-Encoding`, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim();
+        if name.eq_ignore_ascii_case("zstd") {
+            Some(Encoding::Zstd)
+        } else if name.eq_ignore_ascii_case("gzip") || name.eq_ignore_ascii_case("x-gzip") {
+            Some(Encoding::Gzip)
+        } else {
+            None
+        }
+    }
+
+    pub fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Encoding::Zstd => Ok(zstd::encode_all(data, zstd::DEFAULT_COMPRESSION_LEVEL)?),
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+        }
+    }
+    /// Decompresses data that must not expand to more than `limit` bytes. Decompression stops as
+    /// soon as the limit is passed, so a small input can't make the caller allocate at will.
+    pub fn decompress(&self, data: &[u8], limit: u64) -> Result<Vec<u8>> {
+        let reader: Box<dyn Read + '_> = match self {
+            Encoding::Zstd => Box::new(zstd::Decoder::new(data)?),
+            Encoding::Gzip => Box::new(GzDecoder::new(data)),
+        };
+        let mut decompressed = Vec::new();
+        reader
+            .take(limit.saturating_add(1))
+            .read_to_end(&mut decompressed)?;
+        if decompressed.len() as u64 > limit {
+            return Err(anyhow!("Data decompresses to more than {} bytes", limit));
+        }
+        Ok(decompressed)
+    }
+}
+
+/// The most `length` bytes can take up once compressed in any encoding, including the framing
+/// both formats add to data that doesn't compress. Lengths too large to bound give `u64::MAX`.
+pub fn max_compressed_length(length: u64) -> u64 {
+    length.saturating_add(length / 128).saturating_add(1024)
+}
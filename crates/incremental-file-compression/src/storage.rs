@@ -0,0 +1,104 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use incremental_file::{block::Block, file::File, storage::Storage};
+
+use crate::encoding::Encoding;
+
+/// Marks block data stored as it is.
+const RAW: u8 = 0;
+/// Marks block data stored compressed with zstd.
+const ZSTD: u8 = 1;
+
+/// Keeps the blocks of another storage compressed with zstd, while reading and writing the raw
+/// data of blocks like any storage. Every stored block starts with a byte telling whether the rest
+/// is compressed, blocks that don't shrink are stored as they are. Files are passed through
+/// unchanged.
+///
+/// The inner storage holds other bytes than the data of a block, so it must not check them
+/// against the block, use `FileSystemStorage::with_validation(false)` for instance. This storage
+/// checks the length and hash of every block it reads after decompressing it instead. Decompressed
+/// data is never longer than its block, so broken data can't make a read allocate at will.
+/// Compression runs on the blocking thread pool, away from the async threads.
+pub struct CompressedStorage<S: Storage> {
+    inner: S,
+}
+
+impl<S: Storage> CompressedStorage<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+#[async_trait]
+impl<S: Storage> Storage for CompressedStorage<S> {
+    async fn get_file(&self, hash: &str) -> Result<Option<File>> {
+        self.inner.get_file(hash).await
+    }
+    async fn file_exists(&self, hash: &str) -> Result<bool> {
+        self.inner.file_exists(hash).await
+    }
+    async fn upsert_file(&mut self, file: &File) -> Result<()> {
+        self.inner.upsert_file(file).await
+    }
+    async fn remove_file(&mut self, hash: &str) -> Result<()> {
+        self.inner.remove_file(hash).await
+    }
+
+    async fn get_block_data(&self, block: &Block) -> Result<Option<Vec<u8>>> {
+        let stored = match self.inner.get_block_data(block).await? {
+            Some(stored) => stored,
+            None => return Ok(None),
+        };
+        let length = block.length;
+        let data = tokio::task::spawn_blocking(move || match stored.split_first() {
+            Some((&RAW, data)) => Some(Ok(data.to_vec())),
+            Some((&ZSTD, compressed)) => Some(Encoding::Zstd.decompress(compressed, length)),
+            _ => None,
+        })
+        .await?
+        .ok_or_else(|| {
+            anyhow!(
+                "Block with hash {} isn't stored by a compressed storage",
+                block.hash
+            )
+        })??;
+        block.validate(&data)?;
+        Ok(Some(data))
+    }
+    async fn block_exists(&self, block: &Block) -> Result<bool> {
+        self.inner.block_exists(block).await
+    }
+    async fn upsert_block_data<D: AsRef<[u8]> + Send>(
+        &mut self,
+        block: &Block,
+        data: D,
+    ) -> Result<()> {
+        let data = data.as_ref().to_vec();
+        let length = block.length;
+        let stored = tokio::task::spawn_blocking(move || -> Result<Vec<u8>> {
+            // Data longer than its block is kept as it is, it couldn't be decompressed again
+            let compressed = if data.len() as u64 <= length {
+                Some(Encoding::Zstd.compress(&data)?)
+            } else {
+                None
+            };
+            Ok(match compressed {
+                Some(compressed) if compressed.len() < data.len() => {
+                    [&[ZSTD], &compressed[..]].concat()
+                }
+                _ => [&[RAW], &data[..]].concat(),
+            })
+        })
+        .await??;
+        self.inner.upsert_block_data(block, stored).await
+    }
+    async fn remove_block_data(&mut self, block: &Block) -> Result<()> {
+        self.inner.remove_block_data(block).await
+    }
+}
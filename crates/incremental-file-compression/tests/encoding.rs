@@ -0,0 +1,56 @@
+use anyhow::Result;
+use incremental_file_compression::encoding::{max_compressed_length, Encoding};
+
+const ENCODINGS: [Encoding; 2] = [Encoding::Zstd, Encoding::Gzip];
+
+#[test]
+fn data_round_trips() -> Result<()> {
+    let text = "a line of a log file that repeats\n"
+        .repeat(100)
+        .into_bytes();
+    for encoding in ENCODINGS {
+        let compressed = encoding.compress(&text)?;
+        assert!(compressed.len() * 5 < text.len(), "{:?}", encoding);
+        assert_eq!(encoding.decompress(&compressed, text.len() as u64)?, text);
+    }
+    Ok(())
+}
+#[test]
+fn decompression_stops_at_limit() -> Result<()> {
+    let zeros = vec![0; 1024 * 1024];
+    for encoding in ENCODINGS {
+        let compressed = encoding.compress(&zeros)?;
+        assert!(encoding.decompress(&compressed, 1024 * 1024 - 1).is_err());
+        assert!(encoding.decompress(b"not compressed", 100).is_err());
+    }
+    Ok(())
+}
+#[test]
+fn incompressible_data_stays_within_bound() -> Result<()> {
+    // Bytes of a simple generator, which neither format can shrink
+    let mut state = 1u32;
+    let noise = (0..100_000)
+        .map(|_| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 24) as u8
+        })
+        .collect::<Vec<u8>>();
+    for encoding in ENCODINGS {
+        let compressed = encoding.compress(&noise)?;
+        assert!(compressed.len() as u64 <= max_compressed_length(noise.len() as u64));
+    }
+    Ok(())
+}
+#[test]
+fn bound_saturates_for_huge_lengths() {
+    assert_eq!(max_compressed_length(u64::MAX), u64::MAX);
+    assert_eq!(max_compressed_length(u64::MAX - 1024), u64::MAX);
+}
+#[test]
+fn names_match_content_encoding() {
+    for encoding in ENCODINGS {
+        assert_eq!(Encoding::from_name(encoding.name()), Some(encoding));
+    }
+    assert_eq!(Encoding::from_name(" GZIP"), Some(Encoding::Gzip));
+    assert_eq!(Encoding::from_name("br"), None);
+}
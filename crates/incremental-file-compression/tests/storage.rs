@@ -0,0 +1,111 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use incremental_file::{
+    block::Block,
+    file::File,
+    storage::{MemoryStorage, Storage},
+};
+use incremental_file_compression::{encoding::Encoding, storage::CompressedStorage};
+use incremental_file_converter_json::JsonConverter;
+use incremental_file_local::storage::FileSystemStorage;
+
+fn log_lines(count: usize) -> Vec<u8> {
+    (0..count)
+        .map(|line| format!("2024-01-01 00:00:{:02} request served\n", line % 60))
+        .collect::<String>()
+        .into_bytes()
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "incremental-file-compression-{}-{}",
+        name,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+async fn stored_length(storage: &CompressedStorage<MemoryStorage>, block: &Block) -> Result<usize> {
+    let stored = storage
+        .inner()
+        .get_block_data(block)
+        .await?
+        .context("Block doesn't exist")?;
+    Ok(stored.len())
+}
+
+#[tokio::test]
+async fn blocks_are_compressed_at_rest() -> Result<()> {
+    let data = log_lines(500);
+    let mut storage = CompressedStorage::new(MemoryStorage::new());
+    let file = File::from_data(&data, 1000, &mut storage).await?;
+
+    for block in &file.blocks {
+        assert!(stored_length(&storage, block).await? * 4 < block.length as usize);
+    }
+    file.validate(&storage).await?;
+    assert_eq!(file.data(&storage).await?, data);
+    Ok(())
+}
+#[tokio::test]
+async fn blocks_that_dont_shrink_are_stored_raw() -> Result<()> {
+    let mut storage = CompressedStorage::new(MemoryStorage::new());
+    let block = Block::from_data([7, 1, 9]);
+    storage.upsert_block_data(&block, [7, 1, 9]).await?;
+    assert_eq!(stored_length(&storage, &block).await?, 4);
+    assert_eq!(storage.get_block_data(&block).await?, Some(vec![7, 1, 9]));
+    assert!(storage.block_exists(&block).await?);
+
+    storage.remove_block_data(&block).await?;
+    assert_eq!(storage.get_block_data(&block).await?, None);
+    Ok(())
+}
+#[tokio::test]
+async fn decompression_is_limited_to_block_length() -> Result<()> {
+    let block = Block::from_data(log_lines(10));
+    let mut inner = MemoryStorage::new();
+    let compressed = Encoding::Zstd.compress(&log_lines(10_000))?;
+    inner
+        .upsert_block_data(&block, [&[1], &compressed[..]].concat())
+        .await?;
+    let storage = CompressedStorage::new(inner);
+    assert!(storage.get_block_data(&block).await.is_err());
+    Ok(())
+}
+#[tokio::test]
+async fn blocks_are_validated_after_decompression() -> Result<()> {
+    let block = Block::from_data(log_lines(100));
+    let mut corrupt = log_lines(100);
+    corrupt[0] ^= 1;
+    let compressed = Encoding::Zstd.compress(&corrupt)?;
+    for stored in [
+        [&[1], &compressed[..]].concat(),
+        [&[0], &corrupt[..]].concat(),
+    ] {
+        let mut inner = MemoryStorage::new();
+        inner.upsert_block_data(&block, stored).await?;
+        let storage = CompressedStorage::new(inner);
+        assert!(storage.get_block_data(&block).await.is_err());
+    }
+    Ok(())
+}
+#[tokio::test]
+async fn wraps_file_system_storage() -> Result<()> {
+    let dir = temp_dir("local");
+    let data = log_lines(500);
+    let local = FileSystemStorage::new(dir.clone(), JsonConverter {}).with_validation(false);
+    let mut storage = CompressedStorage::new(local);
+    let file = File::from_data(&data, 1000, &mut storage).await?;
+    storage.upsert_file(&file).await?;
+
+    let on_disk: u64 = std::fs::read_dir(dir.join("blocks"))?
+        .map(|entry| Ok(entry?.metadata()?.len()))
+        .sum::<Result<u64>>()?;
+    assert!(on_disk * 4 < data.len() as u64);
+    let file = storage.get_file(&file.hash).await?.context("File exists")?;
+    assert_eq!(file.data(&storage).await?, data);
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
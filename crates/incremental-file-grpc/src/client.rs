@@ -0,0 +1,170 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::{stream::BoxStream, StreamExt};
+use incremental_file::{
+    acquirer::{AcquireError, Acquirer},
+    block::Block,
+    file::File,
+};
+use tonic::{transport::Channel, Code, Streaming};
+
+use crate::proto::{
+    block_result, block_service_client::BlockServiceClient, from_status, BlockResult,
+    GetBlockRequest, GetBlocksRequest, GetFileRequest, HasBlocksRequest,
+};
+
+/// Messages larger than this are refused, tonic allows only 4 MiB by default.
+pub const MAX_MESSAGE_LENGTH: usize = 64 * 1024 * 1024;
+
+/// Requests blocks and files from a `BlockService`, like the one of a `GrpcServer`. Batches of
+/// blocks are fetched with a single `GetBlocks` call and yielded as the server streams them.
+#[derive(Clone)]
+pub struct GrpcAcquirer {
+    client: BlockServiceClient<Channel>,
+}
+
+impl GrpcAcquirer {
+    /// Connects to a server at an URL like `http://127.0.0.1:50051`.
+    pub async fn connect(url: String) -> Result<Self> {
+        let channel = Channel::from_shared(url)?.connect().await?;
+        Ok(Self::new(channel))
+    }
+    /// Uses any channel, for instance one configured with TLS or lazily connecting.
+    pub fn new(channel: Channel) -> Self {
+        Self {
+            client: BlockServiceClient::new(channel).max_decoding_message_size(MAX_MESSAGE_LENGTH),
+        }
+    }
+
+    /// Requests a file by hash, `None` if the server doesn't have it.
+    pub async fn get_file(&self, hash: &str) -> Result<Option<File>> {
+        let request = GetFileRequest {
+            hash: hash.to_string(),
+        };
+        match self.client.clone().get_file(request).await {
+            Ok(response) => {
+                let file: File = response.into_inner().into();
+                if file.hash != hash {
+                    return Err(AcquireError::InvalidData(hash.to_string()).into());
+                }
+                Ok(Some(file))
+            }
+            Err(status) if status.code() == Code::NotFound => Ok(None),
+            Err(status) => Err(from_status(status).into()),
+        }
+    }
+    /// Asks which of the blocks the server holds, in the order of `blocks`.
+    pub async fn has_blocks(&self, blocks: &[Block]) -> Result<Vec<bool>> {
+        let request = HasBlocksRequest {
+            blocks: blocks.iter().map(Into::into).collect(),
+        };
+        let exists = self
+            .client
+            .clone()
+            .has_blocks(request)
+            .await
+            .map_err(from_status)?
+            .into_inner()
+            .exists;
+        if exists.len() != blocks.len() {
+            return Err(AcquireError::Unavailable(format!(
+                "Server answered for {} of {} blocks",
+                exists.len(),
+                blocks.len()
+            ))
+            .into());
+        }
+        Ok(exists)
+    }
+}
+
+/// Failures of a call about one block, a missing block is reported with its hash.
+fn block_error(error: AcquireError, block: &Block) -> AcquireError {
+    match error {
+        AcquireError::NotFound(_) => AcquireError::NotFound(block.hash.clone()),
+        error => error,
+    }
+}
+
+fn block_data(data: Vec<u8>, block: &Block) -> Result<Vec<u8>> {
+    if data.len() as u64 != block.length {
+        return Err(AcquireError::InvalidData(block.hash.clone()).into());
+    }
+    Ok(data)
+}
+
+/// Progress of a `GetBlocks` call. Once it fails, every block that hasn't arrived yet fails the
+/// same way.
+enum Call {
+    Request(GetBlocksRequest),
+    Receiving(Box<Streaming<BlockResult>>),
+    Failed(AcquireError),
+}
+
+#[async_trait]
+impl Acquirer for GrpcAcquirer {
+    async fn get_block(&self, block: &Block) -> Result<Vec<u8>> {
+        let request = GetBlockRequest {
+            block: Some(block.into()),
+        };
+        match self.client.clone().get_block(request).await {
+            Ok(response) => block_data(response.into_inner().data, block),
+            Err(status) => Err(block_error(from_status(status), block).into()),
+        }
+    }
+    fn get_blocks<'a>(&'a self, blocks: &'a [Block]) -> BoxStream<'a, (usize, Result<Vec<u8>>)> {
+        let request = GetBlocksRequest {
+            blocks: blocks.iter().map(Into::into).collect(),
+        };
+        let state = (Call::Request(request), vec![false; blocks.len()]);
+        futures::stream::unfold(state, move |(mut call, mut received)| async move {
+            loop {
+                call = match call {
+                    Call::Request(request) => match self.client.clone().get_blocks(request).await {
+                        Ok(response) => Call::Receiving(Box::new(response.into_inner())),
+                        Err(status) => Call::Failed(from_status(status)),
+                    },
+                    Call::Receiving(mut stream) => match stream.message().await {
+                        Ok(Some(BlockResult { index, result })) => {
+                            let index = index as usize;
+                            match blocks.get(index).filter(|_| !received[index]) {
+                                Some(block) => {
+                                    received[index] = true;
+                                    let result = match result {
+                                        Some(block_result::Result::Data(data)) => {
+                                            block_data(data, block)
+                                        }
+                                        Some(block_result::Result::Error(error)) => {
+                                            Err(block_error(error.into(), block).into())
+                                        }
+                                        None => Err(AcquireError::Unavailable(
+                                            "Server sent a result without data".to_string(),
+                                        )
+                                        .into()),
+                                    };
+                                    let state = (Call::Receiving(stream), received);
+                                    return Some(((index, result), state));
+                                }
+                                None => Call::Failed(AcquireError::Unavailable(format!(
+                                    "Server sent block {} which wasn't outstanding",
+                                    index
+                                ))),
+                            }
+                        }
+                        Ok(None) => Call::Failed(AcquireError::Unavailable(
+                            "Server ended the stream before sending every block".to_string(),
+                        )),
+                        Err(status) => Call::Failed(from_status(status)),
+                    },
+                    Call::Failed(error) => {
+                        let index = received.iter().position(|received| !received)?;
+                        received[index] = true;
+                        let result = Err(block_error(error.clone(), &blocks[index]).into());
+                        return Some(((index, result), (Call::Failed(error), received)));
+                    }
+                }
+            }
+        })
+        .boxed()
+    }
+}
@@ -0,0 +1,91 @@
+//! Messages and service stubs generated from `proto/incremental_file.proto`, with conversions
+//! from and to the types of `incremental-file`.
+
+use incremental_file::{acquirer::AcquireError, block, file};
+use tonic::{Code, Status};
+
+tonic::include_proto!("incremental_file");
+
+impl From<&block::Block> for Block {
+    fn from(block: &block::Block) -> Self {
+        Self {
+            length: block.length,
+            hash: block.hash.clone(),
+        }
+    }
+}
+impl From<Block> for block::Block {
+    fn from(block: Block) -> Self {
+        block::Block::new(block.length, block.hash)
+    }
+}
+
+impl From<&file::File> for File {
+    fn from(file: &file::File) -> Self {
+        Self {
+            blocks: file.blocks.iter().map(Into::into).collect(),
+            hash: file.hash.clone(),
+            signature: file.signature.clone(),
+        }
+    }
+}
+impl From<File> for file::File {
+    fn from(file: File) -> Self {
+        let mut converted =
+            file::File::new(file.blocks.into_iter().map(Into::into).collect(), file.hash);
+        converted.signature = file.signature;
+        converted
+    }
+}
+
+impl From<AcquireError> for Error {
+    fn from(error: AcquireError) -> Self {
+        let (kind, message) = match error {
+            AcquireError::NotFound(message) => (error::Kind::NotFound, message),
+            AcquireError::Unavailable(message) => (error::Kind::Unavailable, message),
+            AcquireError::Rejected(message) => (error::Kind::Rejected, message),
+            AcquireError::InvalidData(message) => (error::Kind::InvalidData, message),
+        };
+        Self {
+            kind: kind.into(),
+            message,
+        }
+    }
+}
+impl From<Error> for AcquireError {
+    fn from(error: Error) -> Self {
+        // Kinds added later are unknown to older peers and taken for a passing failure
+        match error::Kind::try_from(error.kind) {
+            Ok(error::Kind::NotFound) => AcquireError::NotFound(error.message),
+            Ok(error::Kind::Unavailable) | Err(_) => AcquireError::Unavailable(error.message),
+            Ok(error::Kind::Rejected) => AcquireError::Rejected(error.message),
+            Ok(error::Kind::InvalidData) => AcquireError::InvalidData(error.message),
+        }
+    }
+}
+
+/// The status unary calls fail with, see `Error` for the codes.
+pub fn to_status(error: AcquireError) -> Status {
+    match error {
+        AcquireError::NotFound(message) => Status::not_found(message),
+        AcquireError::Unavailable(message) => Status::unavailable(message),
+        AcquireError::Rejected(message) => Status::invalid_argument(message),
+        AcquireError::InvalidData(message) => Status::data_loss(message),
+    }
+}
+/// Reads the status a call failed with. Codes that don't map to a kind are taken for a passing
+/// failure, unless they say the server will keep refusing the request.
+pub fn from_status(status: Status) -> AcquireError {
+    let message = status.message().to_string();
+    match status.code() {
+        Code::NotFound => AcquireError::NotFound(message),
+        Code::InvalidArgument
+        | Code::PermissionDenied
+        | Code::Unauthenticated
+        | Code::Unimplemented
+        | Code::FailedPrecondition
+        | Code::OutOfRange => AcquireError::Rejected(message),
+        Code::DataLoss => AcquireError::InvalidData(message),
+        _ => AcquireError::Unavailable(format!("{}: {}", status.code(), message)),
+    }
+}
@@ -0,0 +1,153 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures::{stream::BoxStream, StreamExt};
+use incremental_file::{
+    acquirer::AcquireError,
+    block::{is_valid_hash, Block},
+    storage::Storage,
+};
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{transport::Server, Request, Response, Status};
+
+use crate::proto::{
+    self, block_result,
+    block_service_server::{BlockService, BlockServiceServer},
+    to_status, BlockData, BlockResult, GetBlockRequest, GetBlocksRequest, GetFileRequest,
+    HasBlocksRequest, HasBlocksResponse,
+};
+
+/// Blocks of a `GetBlocks` call read from the storage at once.
+const CONCURRENCY: usize = 8;
+
+/// Serves the blocks and files of a storage as the `BlockService` of
+/// `proto/incremental_file.proto`. `serve` runs a server of its own, to mount the service next to
+/// others wrap the server into a `BlockServiceServer` instead.
+///
+/// Malformed hashes are not found without asking the storage, and storage failures are logged
+/// while clients only learn that the storage is unavailable.
+pub struct GrpcServer<S: Storage> {
+    storage: Arc<S>,
+}
+
+impl<S: Storage + 'static> GrpcServer<S> {
+    pub fn new(storage: S) -> Self {
+        Self {
+            storage: Arc::new(storage),
+        }
+    }
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    /// Accepts connections until the listener fails.
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> Result<()> {
+        Server::builder()
+            .add_service(BlockServiceServer::from_arc(self))
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await?;
+        Ok(())
+    }
+}
+
+async fn get_block_data<S: Storage>(storage: &S, block: &Block) -> Result<Vec<u8>, AcquireError> {
+    if !is_valid_hash(&block.hash) {
+        return Err(AcquireError::NotFound(block.hash.clone()));
+    }
+    match storage.get_block_data(block).await {
+        Ok(Some(data)) => Ok(data),
+        Ok(None) => Err(AcquireError::NotFound(block.hash.clone())),
+        Err(err) => {
+            log::warn!("Failed to read block {}: {:#}", block.hash, err);
+            Err(storage_failed())
+        }
+    }
+}
+
+/// What clients are told about a failing storage, the details stay in the server's log.
+fn storage_failed() -> AcquireError {
+    AcquireError::Unavailable("The storage failed".to_string())
+}
+
+#[tonic::async_trait]
+impl<S: Storage + 'static> BlockService for GrpcServer<S> {
+    async fn get_block(
+        &self,
+        request: Request<GetBlockRequest>,
+    ) -> Result<Response<BlockData>, Status> {
+        let block = request
+            .into_inner()
+            .block
+            .ok_or_else(|| Status::invalid_argument("Request has no block"))?;
+        let data = get_block_data(&*self.storage, &block.into())
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(BlockData { data }))
+    }
+
+    type GetBlocksStream = BoxStream<'static, Result<BlockResult, Status>>;
+
+    async fn get_blocks(
+        &self,
+        request: Request<GetBlocksRequest>,
+    ) -> Result<Response<Self::GetBlocksStream>, Status> {
+        let storage = self.storage.clone();
+        let blocks = request.into_inner().blocks;
+        let results = futures::stream::iter(blocks.into_iter().enumerate())
+            .map(move |(index, block)| {
+                let storage = storage.clone();
+                async move {
+                    let result = match get_block_data(&*storage, &block.into()).await {
+                        Ok(data) => block_result::Result::Data(data),
+                        Err(error) => block_result::Result::Error(error.into()),
+                    };
+                    Ok(BlockResult {
+                        index: index as u32,
+                        result: Some(result),
+                    })
+                }
+            })
+            .buffer_unordered(CONCURRENCY)
+            .boxed();
+        Ok(Response::new(results))
+    }
+
+    async fn get_file(
+        &self,
+        request: Request<GetFileRequest>,
+    ) -> Result<Response<proto::File>, Status> {
+        let hash = request.into_inner().hash;
+        if !is_valid_hash(&hash) {
+            return Err(to_status(AcquireError::NotFound(hash)));
+        }
+        match self.storage.get_file(&hash).await {
+            Ok(Some(file)) => Ok(Response::new((&file).into())),
+            Ok(None) => Err(to_status(AcquireError::NotFound(hash))),
+            Err(err) => {
+                log::warn!("Failed to read file {}: {:#}", hash, err);
+                Err(to_status(storage_failed()))
+            }
+        }
+    }
+
+    async fn has_blocks(
+        &self,
+        request: Request<HasBlocksRequest>,
+    ) -> Result<Response<HasBlocksResponse>, Status> {
+        let mut exists = Vec::new();
+        for block in request.into_inner().blocks {
+            let block: Block = block.into();
+            let exists_in_storage = if is_valid_hash(&block.hash) {
+                self.storage.block_exists(&block).await
+            } else {
+                Ok(false)
+            };
+            exists.push(exists_in_storage.map_err(|err| {
+                log::warn!("Failed to look up block {}: {:#}", block.hash, err);
+                to_status(storage_failed())
+            })?);
+        }
+        Ok(Response::new(HasBlocksResponse { exists }))
+    }
+}
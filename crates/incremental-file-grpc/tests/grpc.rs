@@ -0,0 +1,178 @@
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::Result;
+use futures::StreamExt;
+use incremental_file::{
+    acquirer::{AcquireError, Acquirer},
+    block::Block,
+    converter::Converter,
+    downloader::Downloader,
+    file::File,
+    storage::{MemoryStorage, Storage},
+};
+use incremental_file_converter_json::JsonConverter;
+use incremental_file_grpc::{client::GrpcAcquirer, server::GrpcServer};
+use incremental_file_local::storage::FileSystemStorage;
+use tokio::net::TcpListener;
+use tonic::transport::Channel;
+
+async fn start<S: Storage + 'static>(storage: S) -> Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}", listener.local_addr()?);
+    tokio::spawn(Arc::new(GrpcServer::new(storage)).serve(listener));
+    Ok(url)
+}
+
+async fn origin(data: &[u8]) -> Result<(MemoryStorage, File)> {
+    let mut storage = MemoryStorage::new();
+    let file = File::from_data(data, 10, &mut storage).await?;
+    storage.upsert_file(&file).await?;
+    Ok((storage, file))
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "incremental-file-grpc-{}-{}",
+        name,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn is_not_found(err: &anyhow::Error, block: &Block) -> bool {
+    matches!(
+        err.downcast_ref::<AcquireError>(),
+        Some(AcquireError::NotFound(hash)) if *hash == block.hash
+    )
+}
+
+#[tokio::test]
+async fn downloads_file_over_loopback() -> Result<()> {
+    let data = (0..255).collect::<Vec<u8>>();
+    let (origin, file) = origin(&data).await?;
+    let url = start(origin).await?;
+    let acquirer = GrpcAcquirer::connect(url).await?;
+
+    let fetched = acquirer.get_file(&file.hash).await?.expect("File exists");
+    assert_eq!(fetched.hash, file.hash);
+    assert_eq!(fetched.blocks.len(), file.blocks.len());
+    let mut storage = MemoryStorage::new();
+    let report = Downloader::new(acquirer)
+        .with_concurrency(4)
+        .with_batch_size(8)
+        .download(&fetched, &mut storage)
+        .await?;
+    assert!(report.is_complete());
+    assert_eq!(file.data(&storage).await?, data);
+    Ok(())
+}
+#[tokio::test]
+async fn streamed_blocks_fail_one_by_one() -> Result<()> {
+    let data = (0..100).collect::<Vec<u8>>();
+    let (origin, file) = origin(&data).await?;
+    let url = start(origin).await?;
+    let acquirer = GrpcAcquirer::connect(url).await?;
+
+    let missing = Block::from_data([1, 2, 3]);
+    let mut blocks = file.blocks.clone();
+    blocks.insert(3, missing.clone());
+    let mut results = acquirer.get_blocks(&blocks).collect::<Vec<_>>().await;
+    results.sort_by_key(|(index, _)| *index);
+    assert_eq!(results.len(), blocks.len());
+    let (_, result) = results.remove(3);
+    assert!(is_not_found(&result.unwrap_err(), &missing));
+    for ((_, result), chunk) in results.into_iter().zip(data.chunks(10)) {
+        assert_eq!(result?, chunk);
+    }
+    Ok(())
+}
+#[tokio::test]
+async fn missing_blocks_and_files_are_not_found() -> Result<()> {
+    let url = start(MemoryStorage::new()).await?;
+    let acquirer = GrpcAcquirer::connect(url).await?;
+    let block = Block::from_data([1, 2, 3]);
+    assert!(is_not_found(
+        &acquirer.get_block(&block).await.unwrap_err(),
+        &block
+    ));
+    assert!(acquirer.get_file("0123").await?.is_none());
+    Ok(())
+}
+#[tokio::test]
+async fn asks_which_blocks_exist() -> Result<()> {
+    let data = (0..30).collect::<Vec<u8>>();
+    let (origin, file) = origin(&data).await?;
+    let url = start(origin).await?;
+    let acquirer = GrpcAcquirer::connect(url).await?;
+
+    let mut blocks = file.blocks.clone();
+    blocks.push(Block::from_data([1, 2, 3]));
+    assert_eq!(
+        acquirer.has_blocks(&blocks).await?,
+        [true, true, true, false]
+    );
+    Ok(())
+}
+#[tokio::test]
+async fn unreachable_server_is_unavailable() -> Result<()> {
+    // Nobody listens on the port once the listener is gone
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}", listener.local_addr()?);
+    drop(listener);
+    let acquirer = GrpcAcquirer::new(Channel::from_shared(url)?.connect_lazy());
+
+    let blocks = [Block::from_data([1, 2, 3]), Block::from_data([4, 5])];
+    let err = acquirer.get_block(&blocks[0]).await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<AcquireError>(),
+        Some(AcquireError::Unavailable(_))
+    ));
+    let results = acquirer.get_blocks(&blocks).collect::<Vec<_>>().await;
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|(_, result)| result.is_err()));
+    Ok(())
+}
+#[tokio::test]
+async fn paths_outside_the_storage_are_not_found() -> Result<()> {
+    let dir = temp_dir("traversal");
+    // A file manifest next to the storage, which `../..` would reach from its file directory
+    let file = File::from_data([1, 2, 3], 10, &mut MemoryStorage::new()).await?;
+    std::fs::write(dir.join("outside"), JsonConverter {}.serialize_file(&file)?)?;
+    let url = start(FileSystemStorage::new(dir.join("cache"), JsonConverter {})).await?;
+    let acquirer = GrpcAcquirer::connect(url).await?;
+
+    assert!(acquirer.get_file("../../outside").await?.is_none());
+    let block = Block::new(3, "../../outside".to_string());
+    assert!(is_not_found(
+        &acquirer.get_block(&block).await.unwrap_err(),
+        &block
+    ));
+    assert_eq!(acquirer.has_blocks(&[block]).await?, [false]);
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+#[tokio::test]
+async fn storage_errors_are_not_sent_to_clients() -> Result<()> {
+    let dir = temp_dir("failing");
+    // The storage can't create its directories below a regular file
+    std::fs::write(dir.join("cache"), [])?;
+    let url = start(FileSystemStorage::new(dir.join("cache"), JsonConverter {})).await?;
+    let acquirer = GrpcAcquirer::connect(url).await?;
+
+    let block = Block::from_data([1, 2, 3]);
+    let errors = [
+        acquirer.get_block(&block).await.unwrap_err(),
+        acquirer.get_file(&block.hash).await.unwrap_err(),
+        acquirer.has_blocks(&[block]).await.unwrap_err(),
+    ];
+    for err in errors {
+        assert!(matches!(
+            err.downcast_ref::<AcquireError>(),
+            Some(AcquireError::Unavailable(reason)) if reason.ends_with(": The storage failed")
+        ));
+    }
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
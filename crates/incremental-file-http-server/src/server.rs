@@ -0,0 +1,212 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::Result;
+use hyper::{
+    header,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, StatusCode,
+};
+use incremental_file::{
+    block::{is_valid_hash, Block},
+    converter::BoxedConverter,
+    file::File,
+    storage::Storage,
+};
+use incremental_file_compression::encoding::Encoding;
+use tokio::net::TcpListener;
+
+/// Blocks and files are addressed by the hash of their content, so a response never goes stale.
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+/// Serves the blocks and files of a storage over HTTP in the layout `GetAcquirer` expects:
+/// `GET /{block_hash}` answers with the data of a block and `GET /files/{file_hash}` with the
+/// file serialized by the converter. `HEAD` requests are answered as well.
+///
+/// A storage can only look blocks up together with their length, so a block is served once a
+/// file containing it is known to the server, either through `add_file` or because the file was
+/// requested from the storage.
+///
+/// With `with_encodings`, responses are compressed for clients that accept it through
+/// `Accept-Encoding`, as long as compression makes them smaller.
+///
+/// Only well-formed hashes are looked up, anything else in a path is not found.
+pub struct StorageServer<S: Storage> {
+    storage: S,
+    converter: BoxedConverter,
+    encodings: Vec<Encoding>,
+    files: RwLock<HashMap<String, File>>,
+    blocks: RwLock<HashMap<String, Block>>,
+}
+
+impl<S: Storage + 'static> StorageServer<S> {
+    pub fn new(storage: S, converter: BoxedConverter) -> Self {
+        Self {
+            storage,
+            converter,
+            encodings: Vec::new(),
+            files: RwLock::new(HashMap::new()),
+            blocks: RwLock::new(HashMap::new()),
+        }
+    }
+    /// Offers the encodings to clients, preferring earlier ones among those a client accepts
+    /// equally.
+    pub fn with_encodings(mut self, encodings: Vec<Encoding>) -> Self {
+        self.encodings = encodings;
+        self
+    }
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+    /// Makes the file and all of its blocks available.
+    pub fn add_file(&self, file: File) {
+        let mut blocks = self.blocks.write().unwrap();
+        for block in &file.blocks {
+            blocks.insert(block.hash.clone(), block.clone());
+        }
+        self.files.write().unwrap().insert(file.hash.clone(), file);
+    }
+
+    /// Accepts connections until the listener fails.
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> Result<()> {
+        let make_service = make_service_fn(move |_| {
+            let server = self.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let server = server.clone();
+                    async move { Ok::<_, Infallible>(server.respond(request).await) }
+                }))
+            }
+        });
+        hyper::Server::from_tcp(listener.into_std()?)?
+            .serve(make_service)
+            .await?;
+        Ok(())
+    }
+
+    /// Answers a single request, for embedding the server into another service.
+    pub async fn respond(&self, request: Request<Body>) -> Response<Body> {
+        let head = match *request.method() {
+            Method::GET => false,
+            Method::HEAD => true,
+            _ => {
+                return Response::builder()
+                    .status(StatusCode::METHOD_NOT_ALLOWED)
+                    .header(header::ALLOW, "GET, HEAD")
+                    .body(Body::empty())
+                    .unwrap()
+            }
+        };
+        let path = request.uri().path().trim_start_matches('/');
+        let data = match path.split_once('/') {
+            Some(("files", hash)) if is_valid_hash(hash) => self.file_data(hash).await,
+            Some(_) => Ok(None),
+            None if is_valid_hash(path) => self.block_data(path).await,
+            None => Ok(None),
+        };
+        let data = match data {
+            Ok(Some(data)) => data,
+            Ok(None) => return status(StatusCode::NOT_FOUND),
+            Err(_) => return status(StatusCode::INTERNAL_SERVER_ERROR),
+        };
+        let accepted = request
+            .headers()
+            .get(header::ACCEPT_ENCODING)
+            .and_then(|accept| accept.to_str().ok())
+            .and_then(|accept| negotiate(&self.encodings, accept));
+        let (data, encoding) = match accepted {
+            Some(encoding) => compress(data, encoding).await,
+            None => (data, None),
+        };
+
+        let hash = path.rsplit('/').next().unwrap();
+        let mut response = Response::builder()
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .header(header::CONTENT_LENGTH, data.len())
+            .header(header::CACHE_CONTROL, IMMUTABLE);
+        if !self.encodings.is_empty() {
+            response = response.header(header::VARY, "Accept-Encoding");
+        }
+        // Every representation needs an ETag of its own
+        response = match encoding {
+            Some(encoding) => response
+                .header(header::CONTENT_ENCODING, encoding.name())
+                .header(header::ETAG, format!("\"{}-{}\"", hash, encoding.name())),
+            None => response.header(header::ETAG, format!("\"{}\"", hash)),
+        };
+        let body = if head {
+            Body::empty()
+        } else {
+            Body::from(data)
+        };
+        response.body(body).unwrap()
+    }
+
+    async fn block_data(&self, hash: &str) -> Result<Option<Vec<u8>>> {
+        let block = self.blocks.read().unwrap().get(hash).cloned();
+        match block {
+            Some(block) => self.storage.get_block_data(&block).await,
+            None => Ok(None),
+        }
+    }
+    async fn file_data(&self, hash: &str) -> Result<Option<Vec<u8>>> {
+        let file = self.files.read().unwrap().get(hash).cloned();
+        let file = match file {
+            Some(file) => file,
+            None => match self.storage.get_file(hash).await? {
+                Some(file) => {
+                    self.add_file(file.clone());
+                    file
+                }
+                None => return Ok(None),
+            },
+        };
+        Ok(Some(self.converter.serialize_file(&file)?))
+    }
+}
+
+/// Picks the encoding the client accepts with the highest quality, `None` if it accepts none of
+/// them.
+fn negotiate(encodings: &[Encoding], accept: &str) -> Option<Encoding> {
+    let quality = |name: &str| {
+        accept.split(',').find_map(|item| {
+            let mut parts = item.split(';');
+            let coding = parts.next()?.trim();
+            coding.eq_ignore_ascii_case(name).then(|| {
+                parts
+                    .find_map(|parameter| parameter.trim().strip_prefix("q="))
+                    .map_or(1.0, |quality| quality.trim().parse().unwrap_or(0.0))
+            })
+        })
+    };
+    let mut best: Option<(Encoding, f32)> = None;
+    for encoding in encodings {
+        let quality = quality(encoding.name())
+            .or_else(|| quality("*"))
+            .unwrap_or(0.0);
+        if quality > 0.0 && best.is_none_or(|(_, best)| quality > best) {
+            best = Some((*encoding, quality));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+/// Compresses the data off the async threads, keeping it as it is unless that makes it smaller.
+async fn compress(data: Vec<u8>, encoding: Encoding) -> (Vec<u8>, Option<Encoding>) {
+    tokio::task::spawn_blocking(move || match encoding.compress(&data) {
+        Ok(compressed) if compressed.len() < data.len() => (compressed, Some(encoding)),
+        _ => (data, None),
+    })
+    .await
+    .expect("Compression doesn't panic")
+}
+
+fn status(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
@@ -0,0 +1,121 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use incremental_file::{
+    downloader::Downloader,
+    file::File,
+    storage::{MemoryStorage, Storage},
+};
+use incremental_file_compression::encoding::Encoding;
+use incremental_file_converter_json::JsonConverter;
+use incremental_file_http::acquirer::get::GetAcquirer;
+use incremental_file_http_server::server::StorageServer;
+use reqwest::header;
+use tokio::net::TcpListener;
+
+/// Serves a file of log lines, which compresses well, in blocks of 1000 bytes.
+async fn start(encodings: Vec<Encoding>) -> Result<(File, Vec<u8>, String)> {
+    let data = (0..200)
+        .map(|line| format!("2024-01-01 00:00:{:02} request served\n", line % 60))
+        .collect::<String>()
+        .into_bytes();
+    let mut origin = MemoryStorage::new();
+    let file = File::from_data(&data, 1000, &mut origin).await?;
+    origin.upsert_file(&file).await?;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}", listener.local_addr()?);
+    let server = StorageServer::new(origin, Box::new(JsonConverter {})).with_encodings(encodings);
+    let server = Arc::new(server);
+    server.add_file(file.clone());
+    tokio::spawn(server.serve(listener));
+    Ok((file, data, url))
+}
+
+async fn get(url: &str, accept: Option<&str>) -> Result<reqwest::Response> {
+    let mut request = reqwest::Client::new().get(url);
+    if let Some(accept) = accept {
+        request = request.header(header::ACCEPT_ENCODING, accept);
+    }
+    Ok(request.send().await?)
+}
+
+#[tokio::test]
+async fn downloads_compressed_blocks() -> Result<()> {
+    let (file, data, url) = start(vec![Encoding::Zstd, Encoding::Gzip]).await?;
+    for encodings in [vec![Encoding::Zstd], vec![Encoding::Gzip, Encoding::Zstd]] {
+        let acquirer = GetAcquirer::new(url.clone())?.with_encodings(encodings);
+        let mut storage = MemoryStorage::new();
+        let report = Downloader::new(acquirer)
+            .download(&file, &mut storage)
+            .await?;
+        assert!(report.is_complete());
+        assert_eq!(file.data(&storage).await?, data);
+    }
+    Ok(())
+}
+#[tokio::test]
+async fn encoding_is_negotiated() -> Result<()> {
+    let (file, data, url) = start(vec![Encoding::Zstd, Encoding::Gzip]).await?;
+    let block = &file.blocks[0];
+    let block_url = format!("{}/{}", url, block.hash);
+    let cases = [
+        (Some("zstd, gzip"), Some(Encoding::Zstd)),
+        (Some("gzip, zstd;q=0.5"), Some(Encoding::Gzip)),
+        (Some("*"), Some(Encoding::Zstd)),
+        (Some("br, zstd;q=0"), None),
+        (None, None),
+    ];
+    for (accept, expected) in cases {
+        let response = get(&block_url, accept).await?;
+        let headers = response.headers().clone();
+        assert_eq!(headers[header::VARY], "Accept-Encoding");
+        let body = response.bytes().await?;
+        match expected {
+            Some(encoding) => {
+                assert_eq!(headers[header::CONTENT_ENCODING], encoding.name());
+                assert_eq!(
+                    headers[header::ETAG],
+                    format!("\"{}-{}\"", block.hash, encoding.name())
+                );
+                assert!(body.len() < 500);
+                assert_eq!(encoding.decompress(&body, block.length)?, data[..1000]);
+            }
+            None => {
+                assert!(
+                    headers.get(header::CONTENT_ENCODING).is_none(),
+                    "{:?}",
+                    accept
+                );
+                assert_eq!(body, data[..1000]);
+            }
+        }
+    }
+    Ok(())
+}
+#[tokio::test]
+async fn blocks_that_dont_shrink_are_sent_as_they_are() -> Result<()> {
+    let mut origin = MemoryStorage::new();
+    let file = File::from_data([7, 1, 9], 10, &mut origin).await?;
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}", listener.local_addr()?);
+    let server =
+        StorageServer::new(origin, Box::new(JsonConverter {})).with_encodings(vec![Encoding::Zstd]);
+    let server = Arc::new(server);
+    server.add_file(file.clone());
+    tokio::spawn(server.serve(listener));
+
+    let response = get(&format!("{}/{}", url, file.blocks[0].hash), Some("zstd")).await?;
+    assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+    assert_eq!(response.bytes().await?, [7, 1, 9][..]);
+    Ok(())
+}
+#[tokio::test]
+async fn compression_is_off_by_default() -> Result<()> {
+    let (file, data, url) = start(Vec::new()).await?;
+    let response = get(&format!("{}/{}", url, file.blocks[0].hash), Some("zstd")).await?;
+    assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+    assert!(response.headers().get(header::VARY).is_none());
+    assert_eq!(response.bytes().await?, data[..1000]);
+    Ok(())
+}
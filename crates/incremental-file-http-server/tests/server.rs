@@ -0,0 +1,129 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use incremental_file::{
+    converter::Converter,
+    downloader::Downloader,
+    file::File,
+    storage::{MemoryStorage, Storage},
+};
+use incremental_file_converter_json::JsonConverter;
+use incremental_file_http::acquirer::get::GetAcquirer;
+use incremental_file_http_server::server::StorageServer;
+use incremental_file_local::storage::FileSystemStorage;
+use reqwest::{header, StatusCode};
+use tokio::net::TcpListener;
+
+async fn start(storage: MemoryStorage) -> Result<(Arc<StorageServer<MemoryStorage>>, String)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}", listener.local_addr()?);
+    let server = Arc::new(StorageServer::new(storage, Box::new(JsonConverter {})));
+    tokio::spawn(server.clone().serve(listener));
+    Ok((server, url))
+}
+
+#[tokio::test]
+async fn serves_blocks_to_get_acquirer() -> Result<()> {
+    let data = (0..100).collect::<Vec<u8>>();
+    let mut origin = MemoryStorage::new();
+    let file = File::from_data(&data, 10, &mut origin).await?;
+    let (server, url) = start(origin).await?;
+    server.add_file(file.clone());
+
+    let mut storage = MemoryStorage::new();
+    let report = Downloader::new(GetAcquirer::new(url)?)
+        .download(&file, &mut storage)
+        .await?;
+    assert!(report.is_complete());
+    assert_eq!(file.data(&storage).await?, data);
+    Ok(())
+}
+#[tokio::test]
+async fn block_responses_are_immutable() -> Result<()> {
+    let data = (0..100).collect::<Vec<u8>>();
+    let mut origin = MemoryStorage::new();
+    let file = File::from_data(&data, 10, &mut origin).await?;
+    let (server, url) = start(origin).await?;
+    server.add_file(file.clone());
+
+    let block = &file.blocks[3];
+    let response = reqwest::get(format!("{}/{}", url, block.hash)).await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_LENGTH], "10");
+    assert_eq!(
+        response.headers()[header::CACHE_CONTROL],
+        "public, max-age=31536000, immutable"
+    );
+    assert_eq!(response.bytes().await?, data[30..40]);
+
+    let response = reqwest::Client::new()
+        .head(format!("{}/{}", url, block.hash))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_LENGTH], "10");
+    assert!(response.bytes().await?.is_empty());
+    Ok(())
+}
+#[tokio::test]
+async fn serves_files_from_storage() -> Result<()> {
+    let data = (0..100).collect::<Vec<u8>>();
+    let mut origin = MemoryStorage::new();
+    let file = File::from_data(&data, 10, &mut origin).await?;
+    origin.upsert_file(&file).await?;
+    let (_server, url) = start(origin).await?;
+
+    let response = reqwest::get(format!("{}/files/{}", url, file.hash)).await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let served = JsonConverter {}.deserialize_file(&response.bytes().await?)?;
+    assert_eq!(served.hash, file.hash);
+    assert_eq!(served.blocks.len(), file.blocks.len());
+
+    // The blocks of the requested file are available from now on
+    let response = reqwest::get(format!("{}/{}", url, file.blocks[0].hash)).await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    Ok(())
+}
+#[tokio::test]
+async fn unknown_hashes_are_not_found() -> Result<()> {
+    let (_server, url) = start(MemoryStorage::new()).await?;
+    for path in ["0123", "files/0123", "nested/0123"] {
+        let response = reqwest::get(format!("{}/{}", url, path)).await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+    let response = reqwest::Client::new().post(&url).send().await?;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    Ok(())
+}
+#[tokio::test]
+async fn paths_outside_the_storage_are_not_served() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!(
+        "incremental-file-http-server-traversal-{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir)?;
+    // A file manifest next to the storage, which `files/../..` would reach from its file directory
+    let file = File::from_data([1, 2, 3], 10, &mut MemoryStorage::new()).await?;
+    std::fs::write(dir.join("outside"), JsonConverter {}.serialize_file(&file)?)?;
+    let storage = FileSystemStorage::new(dir.join("storage"), JsonConverter {});
+    let server = StorageServer::new(storage, Box::new(JsonConverter {}));
+
+    for path in [
+        "/files/../../outside",
+        "/files/..%2F..%2Foutside",
+        "/files/../../missing",
+    ] {
+        let request = hyper::Request::get(path).body(hyper::Body::empty())?;
+        let response = server.respond(request).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", path);
+    }
+    let hash = file.hash.to_uppercase();
+    let request = hyper::Request::get(format!("/files/{}", hash)).body(hyper::Body::empty())?;
+    assert_eq!(
+        server.respond(request).await.status(),
+        StatusCode::NOT_FOUND
+    );
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -1,21 +1,63 @@
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
-use incremental_file::{acquirer::Acquirer, block::Block};
+use incremental_file::{
+    acquirer::{AcquireError, Acquirer},
+    block::Block,
+};
+use incremental_file_compression::encoding::{max_compressed_length, Encoding};
+use reqwest::{header, Client};
 
+use super::{read_body, status_error};
+
+/// Requests every block from `{url}/{hash}`. All requests go through one client, so connections
+/// are kept alive and reused between blocks.
+///
+/// Only successful responses with a body of exactly `Block::length` bytes are accepted, larger
+/// bodies are abandoned as soon as they exceed it. Unsuccessful responses are reported as
+/// `AcquireError`s.
+///
+/// Compressed responses are decompressed before any of these checks, whether or not compression
+/// was asked for through `with_encodings`, so block hashes are always over the raw data.
 pub struct GetAcquirer {
     pub url: String,
+    client: Client,
+    verify_hash: bool,
+    encodings: Vec<Encoding>,
 }
 
 impl GetAcquirer {
     pub fn new(url: String) -> Result<Self> {
-        if url.starts_with("http://") && !url.starts_with("https://") == false {
+        Self::with_client(url, Client::new())
+    }
+    /// Uses a client configured by the caller, for example through `client::ClientBuilder`.
+    pub fn with_client(url: String, client: Client) -> Result<Self> {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
             return Err(anyhow!("URL must start with http:// or https://"));
         }
-        if url.ends_with("/") {
+        if url.ends_with('/') {
             return Err(anyhow!("URL must not end in /"));
         }
 
-        Ok(Self { url })
+        Ok(Self {
+            url,
+            client,
+            verify_hash: false,
+            encodings: Vec::new(),
+        })
+    }
+    /// Checks the hash of every block before returning it, for callers that don't validate blocks
+    /// themselves. The `Downloader` always validates.
+    pub fn with_hash_verification(mut self, verify_hash: bool) -> Self {
+        self.verify_hash = verify_hash;
+        self
+    }
+    /// Asks the server to compress blocks with any of the encodings, preferring earlier ones.
+    pub fn with_encodings(mut self, encodings: Vec<Encoding>) -> Self {
+        self.encodings = encodings;
+        self
+    }
+    pub fn client(&self) -> &Client {
+        &self.client
     }
 }
 
@@ -23,9 +65,70 @@ impl GetAcquirer {
 impl Acquirer for GetAcquirer {
     async fn get_block(&self, block: &Block) -> Result<Vec<u8>> {
         let url = format!("{}/{}", self.url, block.hash);
-        let response = reqwest::get(&url).await?;
-        let bytes = response.bytes().await?;
-        let data: &[u8] = bytes.as_ref();
-        Ok(data.to_vec())
+        let mut request = self.client.get(&url);
+        if let Some(accept) = accept_encoding(&self.encodings) {
+            request = request.header(header::ACCEPT_ENCODING, accept);
+        }
+        let mut response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(status_error(status, &url, &block.hash).into());
+        }
+        let encoding = match response.headers().get(header::CONTENT_ENCODING) {
+            None => None,
+            Some(value) => {
+                let name = value.to_str().unwrap_or_default().trim();
+                if name.eq_ignore_ascii_case("identity") {
+                    None
+                } else {
+                    Some(Encoding::from_name(name).ok_or_else(|| {
+                        AcquireError::Rejected(format!("{} is encoded with {:?}", url, value))
+                    })?)
+                }
+            }
+        };
+        let invalid = || AcquireError::InvalidData(block.hash.clone());
+        let limit = match encoding {
+            Some(_) => max_compressed_length(block.length),
+            None => block.length,
+        };
+        if response
+            .content_length()
+            .is_some_and(|length| length > limit || (encoding.is_none() && length != block.length))
+        {
+            return Err(invalid().into());
+        }
+        let mut data = read_body(&mut response, limit).await?.ok_or_else(invalid)?;
+        if let Some(encoding) = encoding {
+            data = encoding
+                .decompress(&data, block.length)
+                .map_err(|_| invalid())?;
+        }
+        if data.len() as u64 != block.length {
+            return Err(invalid().into());
+        }
+        if self.verify_hash && block.validate(&data).is_err() {
+            return Err(invalid().into());
+        }
+        Ok(data)
+    }
+}
+
+/// Lists the encodings with falling quality, so servers can tell which one is preferred.
+fn accept_encoding(encodings: &[Encoding]) -> Option<String> {
+    if encodings.is_empty() {
+        return None;
     }
+    let accepted = encodings
+        .iter()
+        .enumerate()
+        .map(|(index, encoding)| {
+            let quality = 10usize.saturating_sub(index).max(1);
+            match quality {
+                10 => encoding.name().to_string(),
+                quality => format!("{};q=0.{}", encoding.name(), quality),
+            }
+        })
+        .collect::<Vec<_>>();
+    Some(accepted.join(", "))
 }
@@ -1 +1,44 @@
+use anyhow::Result;
+use incremental_file::acquirer::AcquireError;
+use reqwest::{Response, StatusCode};
+
 pub mod get;
+pub mod range;
+
+/// Most bytes reserved for a response before it arrives, block lengths come from metadata that
+/// may claim far more than the server sends.
+const MAX_PREALLOCATION: u64 = 1 << 20;
+
+/// Reads the body of a response as long as it is at most `limit` bytes long, `None` once it turns
+/// out to be longer. Longer bodies are abandoned without reading the rest.
+pub(crate) async fn read_body(response: &mut Response, limit: u64) -> Result<Option<Vec<u8>>> {
+    if response
+        .content_length()
+        .is_some_and(|length| length > limit)
+    {
+        return Ok(None);
+    }
+    let mut data = Vec::with_capacity(limit.min(MAX_PREALLOCATION) as usize);
+    while let Some(chunk) = response.chunk().await? {
+        if (data.len() + chunk.len()) as u64 > limit {
+            return Ok(None);
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(Some(data))
+}
+
+/// Describes an unsuccessful response in terms of `AcquireError`, so retry policies can tell
+/// missing blocks and refused requests apart from server trouble.
+pub(crate) fn status_error(status: StatusCode, url: &str, hash: &str) -> AcquireError {
+    match status {
+        StatusCode::NOT_FOUND | StatusCode::GONE => AcquireError::NotFound(hash.to_string()),
+        StatusCode::REQUEST_TIMEOUT | StatusCode::TOO_MANY_REQUESTS => {
+            AcquireError::Unavailable(format!("{} responded with {}", url, status))
+        }
+        status if status.is_server_error() => {
+            AcquireError::Unavailable(format!("{} responded with {}", url, status))
+        }
+        status => AcquireError::Rejected(format!("{} responded with {}", url, status)),
+    }
+}
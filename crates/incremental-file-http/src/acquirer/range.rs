@@ -0,0 +1,327 @@
+use std::{collections::HashMap, ops::Range};
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use futures::{stream::BoxStream, StreamExt};
+use incremental_file::{
+    acquirer::{AcquireError, Acquirer},
+    block::Block,
+    file::File,
+};
+use reqwest::{header, Client, StatusCode};
+
+use super::{read_body, status_error};
+
+/// Room allowed for the boundary and headers of each part of a `multipart/byteranges` response.
+const PART_OVERHEAD: u64 = 1024;
+
+/// Reads blocks straight out of a plain file on any HTTP server that supports `Range` requests.
+/// The offset of every block is worked out from the block list of the file. Servers that ignore
+/// `Range` and answer with the whole file are rejected rather than downloading it for every block.
+///
+/// Batches requested through `get_blocks` are merged into as few byte ranges as possible and
+/// fetched with multi-range requests, answered by the server as `multipart/byteranges`.
+pub struct RangeAcquirer {
+    pub url: String,
+    client: Client,
+    offsets: HashMap<String, u64>,
+    max_ranges: usize,
+}
+
+/// A part of the file the server sent back.
+struct Part {
+    range: Range<u64>,
+    data: Vec<u8>,
+}
+
+impl RangeAcquirer {
+    pub fn new(url: String, file: &File) -> Result<Self> {
+        Self::with_client(url, file, Client::new())
+    }
+    pub fn with_client(url: String, file: &File, client: Client) -> Result<Self> {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(anyhow!("URL must start with http:// or https://"));
+        }
+        let mut offsets = HashMap::new();
+        let mut offset = 0;
+        for block in &file.blocks {
+            offsets.entry(block.hash.clone()).or_insert(offset);
+            offset += block.length;
+        }
+        Ok(Self {
+            url,
+            client,
+            offsets,
+            max_ranges: 32,
+        })
+    }
+    /// Sets how many byte ranges a single request may ask for, servers limit the size of headers.
+    pub fn with_max_ranges(mut self, max_ranges: usize) -> Self {
+        self.max_ranges = max_ranges.max(1);
+        self
+    }
+    pub fn offset(&self, block: &Block) -> Option<u64> {
+        self.offsets.get(&block.hash).copied()
+    }
+
+    /// Requests the ranges in one go and returns the parts of the file that came back.
+    async fn get_ranges(&self, ranges: &[Range<u64>]) -> Result<Vec<Part>> {
+        let header = ranges
+            .iter()
+            .map(|range| format!("{}-{}", range.start, range.end - 1))
+            .collect::<Vec<_>>()
+            .join(",");
+        let mut response = self
+            .client
+            .get(&self.url)
+            .header(header::RANGE, format!("bytes={}", header))
+            .send()
+            .await?;
+        let headers = response.headers();
+        let header_value = |name| headers.get(name).and_then(|value| value.to_str().ok());
+        let boundary = header_value(header::CONTENT_TYPE).and_then(multipart_boundary);
+        let content_range = header_value(header::CONTENT_RANGE).map(parse_content_range);
+        match response.status() {
+            StatusCode::PARTIAL_CONTENT => {
+                // Nothing beyond the requested bytes is read, whatever the server sends
+                let length = ranges.iter().fold(0u64, |sum, range| {
+                    sum.saturating_add(range.end - range.start)
+                });
+                let limit = match boundary {
+                    Some(_) => length.saturating_add(ranges.len() as u64 * PART_OVERHEAD),
+                    None => length,
+                };
+                let body = read_body(&mut response, limit)
+                    .await?
+                    .ok_or_else(|| anyhow!("{} sent more than the requested ranges", self.url))?;
+                match (boundary, content_range) {
+                    (Some(boundary), _) => parse_multipart(&body, &boundary),
+                    (None, Some(range)) => Ok(vec![Part {
+                        range: range?,
+                        data: body,
+                    }]),
+                    (None, None) => Err(anyhow!("Partial response without Content-Range")),
+                }
+            }
+            StatusCode::OK => Err(ranges_unsupported(&self.url).into()),
+            StatusCode::RANGE_NOT_SATISFIABLE => Err(AcquireError::Rejected(format!(
+                "{} can't satisfy ranges {}",
+                self.url, header
+            ))
+            .into()),
+            status => Err(status_error(status, &self.url, "").into()),
+        }
+    }
+}
+
+#[async_trait]
+impl Acquirer for RangeAcquirer {
+    async fn get_block(&self, block: &Block) -> Result<Vec<u8>> {
+        let offset = self
+            .offset(block)
+            .ok_or_else(|| AcquireError::NotFound(block.hash.clone()))?;
+        if block.length == 0 {
+            return Ok(Vec::new());
+        }
+        let end = offset + block.length;
+        let mut response = self
+            .client
+            .get(&self.url)
+            .header(header::RANGE, format!("bytes={}-{}", offset, end - 1))
+            .send()
+            .await?;
+        match response.status() {
+            StatusCode::PARTIAL_CONTENT => {}
+            StatusCode::OK => return Err(ranges_unsupported(&self.url).into()),
+            StatusCode::RANGE_NOT_SATISFIABLE => {
+                return Err(AcquireError::NotFound(block.hash.clone()).into())
+            }
+            status => return Err(status_error(status, &self.url, &block.hash).into()),
+        }
+        let invalid = || AcquireError::InvalidData(block.hash.clone());
+        let range = response
+            .headers()
+            .get(header::CONTENT_RANGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| parse_content_range(value).ok());
+        if range != Some(offset..end) {
+            return Err(invalid().into());
+        }
+        let data = read_body(&mut response, block.length)
+            .await?
+            .ok_or_else(invalid)?;
+        if data.len() as u64 != block.length {
+            return Err(invalid().into());
+        }
+        Ok(data)
+    }
+    fn get_blocks<'a>(&'a self, blocks: &'a [Block]) -> BoxStream<'a, (usize, Result<Vec<u8>>)> {
+        let mut immediate = Vec::new();
+        let mut wanted = Vec::new();
+        for (index, block) in blocks.iter().enumerate() {
+            match self.offset(block) {
+                Some(_) if block.length == 0 => immediate.push((index, Ok(Vec::new()))),
+                Some(offset) => wanted.push((index, offset..offset + block.length)),
+                None => immediate.push((
+                    index,
+                    Err(AcquireError::NotFound(block.hash.clone()).into()),
+                )),
+            }
+        }
+        wanted.sort_by_key(|(_, range)| range.start);
+        let spans = merge_ranges(wanted.iter().map(|(_, range)| range.clone()));
+        // Every request asks for a chunk of the spans and delivers the blocks inside them
+        let requests = spans
+            .chunks(self.max_ranges)
+            .map(|spans| {
+                let inside = wanted
+                    .iter()
+                    .filter(|(_, range)| {
+                        spans
+                            .iter()
+                            .any(|span| span.start <= range.start && range.end <= span.end)
+                    })
+                    .cloned()
+                    .collect::<Vec<_>>();
+                (spans.to_vec(), inside)
+            })
+            .collect::<Vec<_>>();
+
+        let fetched = futures::stream::iter(requests)
+            .then(move |(spans, inside)| async move {
+                let parts = self.get_ranges(&spans).await;
+                let results = inside
+                    .into_iter()
+                    .map(|(index, range)| {
+                        let block = &blocks[index];
+                        let result = match &parts {
+                            Ok(parts) => extract(parts, &range)
+                                .filter(|data| block.validate(data).is_ok())
+                                .ok_or_else(|| {
+                                    AcquireError::InvalidData(block.hash.clone()).into()
+                                }),
+                            Err(err) => Err(error_for_block(err, block)),
+                        };
+                        (index, result)
+                    })
+                    .collect::<Vec<_>>();
+                futures::stream::iter(results)
+            })
+            .flatten();
+        futures::stream::iter(immediate).chain(fetched).boxed()
+    }
+}
+
+/// The server answered a range request with the whole file.
+fn ranges_unsupported(url: &str) -> AcquireError {
+    AcquireError::Rejected(format!("{} doesn't support range requests", url))
+}
+
+/// Repeats the error of a request for every block it was meant to deliver.
+fn error_for_block(err: &anyhow::Error, block: &Block) -> anyhow::Error {
+    match err.downcast_ref::<AcquireError>() {
+        Some(AcquireError::NotFound(_)) => AcquireError::NotFound(block.hash.clone()).into(),
+        Some(err) => err.clone().into(),
+        None => anyhow!("{:#}", err),
+    }
+}
+
+/// Merges sorted ranges that overlap or touch.
+fn merge_ranges(ranges: impl Iterator<Item = Range<u64>>) -> Vec<Range<u64>> {
+    let mut merged: Vec<Range<u64>> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Copies a range of the file out of the parts that cover it.
+fn extract(parts: &[Part], range: &Range<u64>) -> Option<Vec<u8>> {
+    let part = parts
+        .iter()
+        .find(|part| part.range.start <= range.start && range.end <= part.range.end)?;
+    let start = (range.start - part.range.start) as usize;
+    let end = (range.end - part.range.start) as usize;
+    part.data.get(start..end).map(|data| data.to_vec())
+}
+
+fn multipart_boundary(content_type: &str) -> Option<String> {
+    let (mime, parameters) = content_type.split_once(';')?;
+    if !mime.trim().eq_ignore_ascii_case("multipart/byteranges") {
+        return None;
+    }
+    parameters.split(';').find_map(|parameter| {
+        let (name, value) = parameter.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("boundary")
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+/// Parses `bytes <first>-<last>/<length>` into the range of the file it covers.
+fn parse_content_range(value: &str) -> Result<Range<u64>> {
+    let invalid = || anyhow!("Invalid Content-Range {}", value);
+    let range = value
+        .trim()
+        .strip_prefix("bytes ")
+        .ok_or_else(invalid)?
+        .split('/')
+        .next()
+        .ok_or_else(invalid)?;
+    let (first, last) = range.split_once('-').ok_or_else(invalid)?;
+    let first = first.trim().parse::<u64>().map_err(|_| invalid())?;
+    let last = last.trim().parse::<u64>().map_err(|_| invalid())?;
+    if last < first {
+        return Err(invalid());
+    }
+    let end = last.checked_add(1).ok_or_else(invalid)?;
+    Ok(first..end)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Splits a `multipart/byteranges` body into its parts. The length of each part is taken from its
+/// `Content-Range` header, so data that happens to contain the boundary can't confuse the parser.
+fn parse_multipart(body: &[u8], boundary: &str) -> Result<Vec<Part>> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut parts = Vec::new();
+    let mut position = find(body, &delimiter).context("Multipart body without boundary")?;
+    loop {
+        position += delimiter.len();
+        let rest = &body[position..];
+        if rest.starts_with(b"--") {
+            return Ok(parts);
+        }
+        let headers_end = find(rest, b"\r\n\r\n").context("Multipart part without headers")?;
+        let headers = std::str::from_utf8(&rest[..headers_end])?;
+        let content_range = headers
+            .lines()
+            .find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.trim()
+                    .eq_ignore_ascii_case("content-range")
+                    .then_some(value)
+            })
+            .context("Multipart part without Content-Range")?;
+        let range = parse_content_range(content_range)?;
+        let data_start = position + headers_end + 4;
+        let data_end = usize::try_from(range.end - range.start)
+            .ok()
+            .and_then(|length| data_start.checked_add(length))
+            .context("Multipart part is longer than its body")?;
+        let data = body
+            .get(data_start..data_end)
+            .context("Multipart part is shorter than its Content-Range")?
+            .to_vec();
+        parts.push(Part { range, data });
+        position = data_end
+            + find(&body[data_end..], &delimiter).context("Unterminated multipart body")?;
+    }
+}
@@ -0,0 +1,144 @@
+use std::{sync::Arc, time::Duration, time::SystemTime};
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION},
+    Certificate, Client, Proxy,
+};
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    ServerName,
+};
+
+/// Configures the `reqwest::Client` used by the acquirers. Build the client once and hand it to
+/// `GetAcquirer::with_client` or `RangeAcquirer::with_client`, every request then shares its
+/// connection pool. Invalid settings are reported by `build`.
+#[derive(Default)]
+pub struct ClientBuilder {
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    proxy: Option<Proxy>,
+    headers: Vec<(String, String)>,
+    user_agent: Option<String>,
+    root_certificates: Vec<Vec<u8>>,
+    pins: Vec<String>,
+}
+
+impl ClientBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Limits the time of a whole request, from connecting until the body is read.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+    pub fn with_proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+    /// Adds a header to every request.
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+    /// Sends `Authorization: Bearer <token>` with every request.
+    pub fn with_bearer_token(self, token: &str) -> Self {
+        self.with_header(AUTHORIZATION.as_str(), &format!("Bearer {}", token))
+    }
+    pub fn with_user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+    /// Trusts a PEM encoded certificate authority on top of the ones of the system.
+    pub fn with_root_certificate(mut self, pem: &[u8]) -> Self {
+        self.root_certificates.push(pem.to_vec());
+        self
+    }
+    /// Only accepts servers presenting a certificate with this SHA-256 fingerprint, given in hex
+    /// with or without colons. Once a certificate is pinned, certificate authorities are not
+    /// consulted at all, so self-signed certificates work as well.
+    pub fn with_pinned_certificate(mut self, sha256: &str) -> Self {
+        self.pins.push(sha256.replace(':', "").to_lowercase());
+        self
+    }
+
+    pub fn build(self) -> Result<Client> {
+        let mut builder = Client::builder();
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(proxy) = self.proxy {
+            builder = builder.proxy(proxy);
+        }
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .with_context(|| format!("Invalid header name {}", name))?;
+            let mut value = HeaderValue::from_str(value)
+                .with_context(|| format!("Invalid value for header {}", name))?;
+            if name == AUTHORIZATION {
+                value.set_sensitive(true);
+            }
+            headers.append(name, value);
+        }
+        builder = builder.default_headers(headers);
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+        for pem in &self.root_certificates {
+            builder = builder.add_root_certificate(Certificate::from_pem(pem)?);
+        }
+        if !self.pins.is_empty() {
+            let pins = self
+                .pins
+                .iter()
+                .map(|pin| {
+                    hex::decode(pin)
+                        .ok()
+                        .filter(|pin| pin.len() == 32)
+                        .ok_or_else(|| anyhow!("Invalid SHA-256 fingerprint {}", pin))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let config = rustls::ClientConfig::builder()
+                .with_safe_defaults()
+                .with_custom_certificate_verifier(Arc::new(PinnedCertificates { pins }))
+                .with_no_client_auth();
+            builder = builder.use_preconfigured_tls(config);
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// Accepts exactly the certificates with one of the pinned fingerprints.
+struct PinnedCertificates {
+    pins: Vec<Vec<u8>>,
+}
+
+impl ServerCertVerifier for PinnedCertificates {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let fingerprint = ring::digest::digest(&ring::digest::SHA256, &end_entity.0);
+        if self.pins.iter().any(|pin| pin == fingerprint.as_ref()) {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General(
+                "Certificate doesn't match any pinned fingerprint".to_string(),
+            ))
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use anyhow::{anyhow, Result};
+use incremental_file::{converter::BoxedConverter, crypto::PublicKey, file::File};
+use reqwest::Client;
+
+/// Fetches files, the lists of blocks, over HTTP and decodes them with a converter. With a public
+/// key, only files carrying a valid signature are returned, so a download can be bootstrapped from
+/// nothing but a URL and a key.
+pub struct FileFetcher {
+    client: Client,
+    converter: BoxedConverter,
+    public_key: Option<PublicKey>,
+    max_size: u64,
+}
+
+impl FileFetcher {
+    pub fn new(converter: BoxedConverter) -> Self {
+        Self::with_client(converter, Client::new())
+    }
+    /// Uses a client configured by the caller, for example through `client::ClientBuilder`.
+    pub fn with_client(converter: BoxedConverter, client: Client) -> Self {
+        Self {
+            client,
+            converter,
+            public_key: None,
+            max_size: 16 * 1024 * 1024,
+        }
+    }
+    /// Requires every file to be signed with the key pair of this public key.
+    pub fn with_public_key(mut self, public_key: PublicKey) -> Self {
+        self.public_key = Some(public_key);
+        self
+    }
+    /// Limits the size of a serialized file, larger responses are abandoned. Defaults to 16 MiB.
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Fetches the file with the given hash from `{url}/files/{hash}`, the layout of
+    /// `incremental-file-http-server`.
+    pub async fn get_file(&self, url: &str, hash: &str) -> Result<File> {
+        let file = self
+            .get_file_from(&format!("{}/files/{}", url.trim_end_matches('/'), hash))
+            .await?;
+        if file.hash != hash {
+            return Err(anyhow!(
+                "Received file with hash {} instead of {}",
+                file.hash,
+                hash
+            ));
+        }
+        Ok(file)
+    }
+    /// Fetches the file at exactly this URL.
+    pub async fn get_file_from(&self, url: &str) -> Result<File> {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(anyhow!("URL must start with http:// or https://"));
+        }
+        let mut response = self.client.get(url).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(anyhow!("{} responded with {}", url, status));
+        }
+        let too_large = || anyhow!("File at {} is larger than {} bytes", url, self.max_size);
+        if response
+            .content_length()
+            .is_some_and(|length| length > self.max_size)
+        {
+            return Err(too_large());
+        }
+        let mut data = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if (data.len() + chunk.len()) as u64 > self.max_size {
+                return Err(too_large());
+            }
+            data.extend_from_slice(&chunk);
+        }
+        let file = self.converter.deserialize_file(&data)?;
+        if let Some(public_key) = &self.public_key {
+            file.verify(public_key)?;
+        }
+        Ok(file)
+    }
+}
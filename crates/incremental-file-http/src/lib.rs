@@ -1 +1,3 @@
 pub mod acquirer;
+pub mod client;
+pub mod file;
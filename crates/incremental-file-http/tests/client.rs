@@ -0,0 +1,107 @@
+mod common;
+
+use std::time::Duration;
+
+use anyhow::Result;
+use common::FileServer;
+use incremental_file::{
+    acquirer::Acquirer, block::Block, downloader::Downloader, file::File, storage::MemoryStorage,
+};
+use incremental_file_http::{acquirer::get::GetAcquirer, client::ClientBuilder};
+use tokio::net::TcpListener;
+
+#[tokio::test]
+async fn requests_carry_configured_headers() -> Result<()> {
+    let data = (0..100).collect::<Vec<u8>>();
+    let server = FileServer::start_blocks(data.clone(), 100).await;
+    let client = ClientBuilder::new()
+        .with_user_agent("incremental-file-test")
+        .with_bearer_token("secret")
+        .with_header("X-Tenant", "blue")
+        .build()?;
+    let acquirer = GetAcquirer::with_client(server.url.clone(), client)?;
+    assert_eq!(acquirer.get_block(&Block::from_data(&data)).await?, data);
+
+    assert_eq!(
+        server.header("user-agent").as_deref(),
+        Some("incremental-file-test")
+    );
+    assert_eq!(
+        server.header("authorization").as_deref(),
+        Some("Bearer secret")
+    );
+    assert_eq!(server.header("x-tenant").as_deref(), Some("blue"));
+    Ok(())
+}
+#[tokio::test]
+async fn blocks_share_one_connection() -> Result<()> {
+    let data = (0..100).collect::<Vec<u8>>();
+    let file = File::from_data(&data, 10, &mut MemoryStorage::new()).await?;
+    let server = FileServer::start_blocks(data, 10).await;
+    let acquirer = GetAcquirer::new(server.url.clone())?;
+    let report = Downloader::new(acquirer)
+        .download(&file, &mut MemoryStorage::new())
+        .await?;
+
+    assert!(report.is_complete());
+    assert_eq!(server.requests(), file.blocks.len());
+    assert_eq!(server.connections(), 1);
+    Ok(())
+}
+#[tokio::test]
+async fn requests_time_out() -> Result<()> {
+    // Accepts connections but never answers
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}", listener.local_addr()?);
+    tokio::spawn(async move {
+        let mut streams = Vec::new();
+        while let Ok((stream, _)) = listener.accept().await {
+            streams.push(stream);
+        }
+    });
+    let client = ClientBuilder::new()
+        .with_timeout(Duration::from_millis(100))
+        .build()?;
+    let acquirer = GetAcquirer::with_client(url, client)?;
+    let result = tokio::time::timeout(
+        Duration::from_secs(5),
+        acquirer.get_block(&Block::from_data([1, 2, 3])),
+    )
+    .await?;
+    assert!(result.is_err());
+    Ok(())
+}
+#[tokio::test]
+async fn pinned_certificate_is_accepted() -> Result<()> {
+    let data = (0..100).collect::<Vec<u8>>();
+    let (server, fingerprint) = FileServer::start_tls(data.clone(), 100).await;
+    let client = ClientBuilder::new()
+        .with_pinned_certificate(&fingerprint)
+        .build()?;
+    let acquirer = GetAcquirer::with_client(server.url.clone(), client)?;
+    assert_eq!(acquirer.get_block(&Block::from_data(&data)).await?, data);
+    Ok(())
+}
+#[tokio::test]
+async fn other_certificates_are_refused() -> Result<()> {
+    let data = (0..100).collect::<Vec<u8>>();
+    let (server, _) = FileServer::start_tls(data.clone(), 100).await;
+    let client = ClientBuilder::new()
+        .with_pinned_certificate(&"ab".repeat(32))
+        .build()?;
+    let acquirer = GetAcquirer::with_client(server.url.clone(), client)?;
+    assert!(acquirer.get_block(&Block::from_data(&data)).await.is_err());
+    assert_eq!(server.requests(), 0);
+    Ok(())
+}
+#[test]
+fn invalid_settings_fail_to_build() {
+    assert!(ClientBuilder::new()
+        .with_header("Bad Header", "value")
+        .build()
+        .is_err());
+    assert!(ClientBuilder::new()
+        .with_pinned_certificate("not hex")
+        .build()
+        .is_err());
+}
@@ -0,0 +1,248 @@
+#![allow(dead_code)]
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use incremental_file::block::Block;
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpListener,
+};
+use tokio_rustls::{
+    rustls::{self, Certificate, PrivateKey},
+    TlsAcceptor,
+};
+
+const BOUNDARY: &str = "3d6b6a416f9b5";
+
+/// A bare bones HTTP/1.1 server that serves one file at `/file`, honoring `Range` requests. Several
+/// ranges are answered as `multipart/byteranges`. Servers started with `start_blocks` also serve
+/// the blocks of the file at `/file/{hash}`.
+pub struct FileServer {
+    pub url: String,
+    state: Arc<State>,
+}
+
+#[derive(Default)]
+struct State {
+    data: Vec<u8>,
+    blocks: HashMap<String, Vec<u8>>,
+    requests: AtomicUsize,
+    connections: AtomicUsize,
+    headers: Mutex<Vec<(String, String)>>,
+}
+
+impl State {
+    fn with_blocks(data: Vec<u8>, block_size: usize) -> State {
+        let blocks = data
+            .chunks(block_size)
+            .map(|chunk| (Block::from_data(chunk).hash, chunk.to_vec()))
+            .collect();
+        State {
+            data,
+            blocks,
+            ..State::default()
+        }
+    }
+}
+
+impl FileServer {
+    pub async fn start(data: Vec<u8>) -> FileServer {
+        Self::serve(State {
+            data,
+            ..State::default()
+        })
+        .await
+    }
+    pub async fn start_blocks(data: Vec<u8>, block_size: usize) -> FileServer {
+        Self::serve(State::with_blocks(data, block_size)).await
+    }
+    async fn serve(state: State) -> FileServer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/file", listener.local_addr().unwrap());
+        let state = Arc::new(state);
+        let server_state = state.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(handle(stream, server_state.clone()));
+            }
+        });
+        FileServer { url, state }
+    }
+    /// Serves blocks over TLS with a fresh self-signed certificate, whose SHA-256 fingerprint is
+    /// returned.
+    pub async fn start_tls(data: Vec<u8>, block_size: usize) -> (FileServer, String) {
+        let certificate =
+            rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let der = certificate.serialize_der().unwrap();
+        let fingerprint = hex::encode(ring::digest::digest(&ring::digest::SHA256, &der));
+        let config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![Certificate(der)],
+                PrivateKey(certificate.serialize_private_key_der()),
+            )
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!(
+            "https://localhost:{}/file",
+            listener.local_addr().unwrap().port()
+        );
+        let state = Arc::new(State::with_blocks(data, block_size));
+        let server_state = state.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+                let state = server_state.clone();
+                tokio::spawn(async move {
+                    // Clients refusing the certificate abort the handshake
+                    if let Ok(stream) = acceptor.accept(stream).await {
+                        handle(stream, state).await;
+                    }
+                });
+            }
+        });
+        (FileServer { url, state }, fingerprint)
+    }
+    pub fn requests(&self) -> usize {
+        self.state.requests.load(Ordering::SeqCst)
+    }
+    pub fn connections(&self) -> usize {
+        self.state.connections.load(Ordering::SeqCst)
+    }
+    /// Value of a header of the most recent request.
+    pub fn header(&self, name: &str) -> Option<String> {
+        self.state
+            .headers
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.clone())
+    }
+}
+
+async fn handle<S: AsyncRead + AsyncWrite + Unpin>(stream: S, state: Arc<State>) {
+    state.connections.fetch_add(1, Ordering::SeqCst);
+    let mut stream = BufReader::new(stream);
+    loop {
+        let mut headers = Vec::new();
+        let mut request_line = String::new();
+        if stream.read_line(&mut request_line).await.unwrap_or(0) == 0 {
+            return;
+        }
+        loop {
+            let mut line = String::new();
+            if stream.read_line(&mut line).await.unwrap_or(0) == 0 {
+                return;
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                headers.push((name.trim().to_string(), value.trim().to_string()));
+            }
+        }
+        let range = headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("range"))
+            .map(|(_, value)| value.clone());
+        *state.headers.lock().unwrap() = headers;
+        state.requests.fetch_add(1, Ordering::SeqCst);
+        let path = request_line.split(' ').nth(1).unwrap_or_default();
+        let (status, headers, body) = match path.strip_prefix("/file/") {
+            Some(hash) => match state.blocks.get(hash) {
+                Some(block) => ("200 OK", String::new(), block.clone()),
+                None => ("404 Not Found", String::new(), Vec::new()),
+            },
+            None => respond(&state.data, range.as_deref()),
+        };
+        let mut response = format!(
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\n{}\r\n",
+            status,
+            body.len(),
+            headers
+        )
+        .into_bytes();
+        response.extend(body);
+        if stream.get_mut().write_all(&response).await.is_err() {
+            return;
+        }
+    }
+}
+
+fn respond(data: &[u8], range: Option<&str>) -> (&'static str, String, Vec<u8>) {
+    let ranges = match range.and_then(|range| range.strip_prefix("bytes=")) {
+        Some(ranges) => ranges
+            .split(',')
+            .map(|range| {
+                let (start, end) = range.trim().split_once('-').unwrap();
+                (
+                    start.parse::<usize>().unwrap(),
+                    end.parse::<usize>().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>(),
+        None => return ("200 OK", String::new(), data.to_vec()),
+    };
+    if ranges.iter().any(|(_, end)| *end >= data.len()) {
+        return ("416 Range Not Satisfiable", String::new(), Vec::new());
+    }
+    if let [(start, end)] = ranges[..] {
+        let headers = format!("Content-Range: bytes {}-{}/{}\r\n", start, end, data.len());
+        return ("206 Partial Content", headers, data[start..=end].to_vec());
+    }
+    let mut body = Vec::new();
+    for (start, end) in ranges {
+        body.extend(
+            format!(
+                "\r\n--{}\r\nContent-Type: application/octet-stream\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                BOUNDARY,
+                start,
+                end,
+                data.len()
+            )
+            .into_bytes(),
+        );
+        body.extend(&data[start..=end]);
+    }
+    body.extend(format!("\r\n--{}--\r\n", BOUNDARY).into_bytes());
+    let headers = format!(
+        "Content-Type: multipart/byteranges; boundary={}\r\n",
+        BOUNDARY
+    );
+    ("206 Partial Content", headers, body)
+}
+
+/// Answers every request with the same raw HTTP response and closes the connection, for servers
+/// that misbehave. Returns the base URL.
+pub async fn serve_raw(response: Vec<u8>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let response = Arc::new(response);
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let response = response.clone();
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                let mut line = String::new();
+                // Skip the request up to its empty line
+                while stream.read_line(&mut line).await.unwrap_or(0) > 0 && line != "\r\n" {
+                    line.clear();
+                }
+                let _ = stream.get_mut().write_all(&response).await;
+            });
+        }
+    });
+    url
+}
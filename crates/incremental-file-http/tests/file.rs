@@ -0,0 +1,94 @@
+mod common;
+
+use anyhow::Result;
+use common::serve_raw;
+use incremental_file::{
+    converter::Converter,
+    crypto::{generate_keypair, get_public_key, parse_public_key},
+    file::File,
+    storage::MemoryStorage,
+};
+use incremental_file_converter_json::JsonConverter;
+use incremental_file_http::file::FileFetcher;
+
+fn response(status: &str, body: &[u8]) -> Vec<u8> {
+    let mut response = format!(
+        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        body.len()
+    )
+    .into_bytes();
+    response.extend_from_slice(body);
+    response
+}
+
+async fn file() -> Result<File> {
+    let data = (0..100).collect::<Vec<u8>>();
+    File::from_data(&data, 10, &mut MemoryStorage::new()).await
+}
+
+#[tokio::test]
+async fn fetches_file_by_hash() -> Result<()> {
+    let file = file().await?;
+    let url = serve_raw(response("200 OK", &JsonConverter {}.serialize_file(&file)?)).await;
+    let fetcher = FileFetcher::new(Box::new(JsonConverter {}));
+    let fetched = fetcher.get_file(&url, &file.hash).await?;
+    assert_eq!(fetched.hash, file.hash);
+    assert_eq!(fetched.blocks.len(), file.blocks.len());
+
+    // A server answering with another file is caught
+    assert!(fetcher.get_file(&url, "0123").await.is_err());
+    Ok(())
+}
+#[tokio::test]
+async fn verifies_signature_with_public_key() -> Result<()> {
+    let keypair = generate_keypair()?;
+    let public_key = parse_public_key(&get_public_key(&keypair)?);
+    let mut file = file().await?;
+    let unsigned = serve_raw(response("200 OK", &JsonConverter {}.serialize_file(&file)?)).await;
+    file.sign(&keypair)?;
+    let signed = serve_raw(response("200 OK", &JsonConverter {}.serialize_file(&file)?)).await;
+
+    let fetcher = FileFetcher::new(Box::new(JsonConverter {})).with_public_key(public_key);
+    let fetched = fetcher
+        .get_file_from(&format!("{}/file.json", signed))
+        .await?;
+    assert_eq!(fetched.signature, file.signature);
+    assert!(fetcher
+        .get_file_from(&format!("{}/file.json", unsigned))
+        .await
+        .is_err());
+
+    // A signed file whose blocks were swapped on the way
+    let mut tampered = file.clone();
+    tampered.blocks.swap(0, 1);
+    let tampered = serve_raw(response(
+        "200 OK",
+        &JsonConverter {}.serialize_file(&tampered)?,
+    ))
+    .await;
+    assert!(fetcher
+        .get_file_from(&format!("{}/file.json", tampered))
+        .await
+        .is_err());
+
+    let other_key = parse_public_key(&get_public_key(&generate_keypair()?)?);
+    let fetcher = FileFetcher::new(Box::new(JsonConverter {})).with_public_key(other_key);
+    assert!(fetcher
+        .get_file_from(&format!("{}/file.json", signed))
+        .await
+        .is_err());
+    Ok(())
+}
+#[tokio::test]
+async fn unsuccessful_and_oversized_responses_fail() -> Result<()> {
+    let file = file().await?;
+    let fetcher = FileFetcher::new(Box::new(JsonConverter {}));
+    let missing = serve_raw(response("404 Not Found", b"not found")).await;
+    assert!(fetcher.get_file(&missing, &file.hash).await.is_err());
+
+    let url = serve_raw(response("200 OK", &JsonConverter {}.serialize_file(&file)?)).await;
+    let fetcher = FileFetcher::new(Box::new(JsonConverter {})).with_max_size(16);
+    assert!(fetcher.get_file(&url, &file.hash).await.is_err());
+    Ok(())
+}
@@ -0,0 +1,174 @@
+mod common;
+
+use anyhow::Result;
+use common::{serve_raw, FileServer};
+use incremental_file::{
+    acquirer::{AcquireError, Acquirer},
+    block::Block,
+};
+use incremental_file_compression::encoding::Encoding;
+use incremental_file_http::acquirer::get::GetAcquirer;
+
+fn response(status: &str, headers: &str, body: &[u8]) -> Vec<u8> {
+    let mut response = format!(
+        "HTTP/1.1 {}\r\n{}Connection: close\r\n\r\n",
+        status, headers
+    )
+    .into_bytes();
+    response.extend_from_slice(body);
+    response
+}
+
+async fn get_block(response: Vec<u8>, block: &Block) -> Result<Vec<u8>> {
+    let url = serve_raw(response).await;
+    GetAcquirer::new(url)?.get_block(block).await
+}
+
+fn acquire_error(result: Result<Vec<u8>>) -> AcquireError {
+    result
+        .unwrap_err()
+        .downcast::<AcquireError>()
+        .expect("Error should be an AcquireError")
+}
+
+#[tokio::test]
+async fn accepts_exact_blocks() -> Result<()> {
+    let data = (0..100).collect::<Vec<u8>>();
+    let server = FileServer::start_blocks(data.clone(), 10).await;
+    let acquirer = GetAcquirer::new(server.url.clone())?.with_hash_verification(true);
+    for chunk in data.chunks(10) {
+        assert_eq!(acquirer.get_block(&Block::from_data(chunk)).await?, chunk);
+    }
+    Ok(())
+}
+#[tokio::test]
+async fn status_codes_are_typed() -> Result<()> {
+    let block = Block::from_data([1, 2, 3]);
+    let cases = [
+        ("404 Not Found", "not found"),
+        ("503 Service Unavailable", "unavailable"),
+        ("403 Forbidden", "rejected"),
+    ];
+    for (status, expected) in cases {
+        let body = b"<html>error page</html>";
+        let headers = format!("Content-Length: {}\r\n", body.len());
+        let err = acquire_error(get_block(response(status, &headers, body), &block).await);
+        let kind = match err {
+            AcquireError::NotFound(_) => "not found",
+            AcquireError::Unavailable(_) => "unavailable",
+            AcquireError::Rejected(_) => "rejected",
+            AcquireError::InvalidData(_) => "invalid",
+        };
+        assert_eq!(kind, expected, "{}", status);
+    }
+    Ok(())
+}
+#[tokio::test]
+async fn content_length_must_match_block() -> Result<()> {
+    let block = Block::from_data([1, 2, 3]);
+    let err = acquire_error(
+        get_block(
+            response("200 OK", "Content-Length: 4\r\n", &[1, 2, 3, 4]),
+            &block,
+        )
+        .await,
+    );
+    assert!(matches!(err, AcquireError::InvalidData(_)));
+    Ok(())
+}
+#[tokio::test]
+async fn oversized_bodies_are_abandoned() -> Result<()> {
+    let block = Block::from_data([1, 2, 3]);
+    // A chunked body without Content-Length that keeps going past the block
+    let body = b"2\r\n\x01\x02\r\n2\r\n\x03\x04\r\n0\r\n\r\n";
+    let err = acquire_error(
+        get_block(
+            response("200 OK", "Transfer-Encoding: chunked\r\n", body),
+            &block,
+        )
+        .await,
+    );
+    assert!(matches!(err, AcquireError::InvalidData(_)));
+    Ok(())
+}
+#[tokio::test]
+async fn short_bodies_are_invalid() -> Result<()> {
+    let block = Block::from_data([1, 2, 3]);
+    let body = b"2\r\n\x01\x02\r\n0\r\n\r\n";
+    let err = acquire_error(
+        get_block(
+            response("200 OK", "Transfer-Encoding: chunked\r\n", body),
+            &block,
+        )
+        .await,
+    );
+    assert!(matches!(err, AcquireError::InvalidData(_)));
+    Ok(())
+}
+#[tokio::test]
+async fn claimed_length_is_not_allocated_up_front() -> Result<()> {
+    let block = Block::new(u64::MAX, Block::from_data([1, 2]).hash);
+    let body = b"2\r\n\x01\x02\r\n0\r\n\r\n";
+    let err = acquire_error(
+        get_block(
+            response("200 OK", "Transfer-Encoding: chunked\r\n", body),
+            &block,
+        )
+        .await,
+    );
+    assert!(matches!(err, AcquireError::InvalidData(_)));
+    Ok(())
+}
+#[tokio::test]
+async fn hash_is_verified_on_request() -> Result<()> {
+    let block = Block::from_data([1, 2, 3]);
+    let wrong = response("200 OK", "Content-Length: 3\r\n", &[3, 2, 1]);
+
+    // Without verification the data is passed on for the caller to validate
+    assert_eq!(get_block(wrong.clone(), &block).await?, [3, 2, 1]);
+
+    let url = serve_raw(wrong).await;
+    let acquirer = GetAcquirer::new(url)?.with_hash_verification(true);
+    let err = acquire_error(acquirer.get_block(&block).await);
+    assert!(matches!(err, AcquireError::InvalidData(_)));
+    Ok(())
+}
+#[tokio::test]
+async fn compressed_bodies_are_decompressed_up_to_block_length() -> Result<()> {
+    let data = b"abcabcabcabcabcabcabcabcabcabc".to_vec();
+    let block = Block::from_data(&data);
+    for encoding in [Encoding::Zstd, Encoding::Gzip] {
+        let compressed = encoding.compress(&data)?;
+        let headers = format!(
+            "Content-Encoding: {}\r\nContent-Length: {}\r\n",
+            encoding.name(),
+            compressed.len()
+        );
+        let ok = response("200 OK", &headers, &compressed);
+        assert_eq!(get_block(ok, &block).await?, data);
+
+        // Expands well past the block, but is refused without being decompressed in full
+        let bomb = encoding.compress(&vec![0; 16 * 1024 * 1024])?;
+        let headers = format!(
+            "Content-Encoding: {}\r\nContent-Length: {}\r\n",
+            encoding.name(),
+            bomb.len()
+        );
+        let err = acquire_error(get_block(response("200 OK", &headers, &bomb), &block).await);
+        assert!(matches!(err, AcquireError::InvalidData(_)));
+    }
+
+    let identity = format!(
+        "Content-Encoding: IDENTITY\r\nContent-Length: {}\r\n",
+        data.len()
+    );
+    assert_eq!(
+        get_block(response("200 OK", &identity, &data), &block).await?,
+        data
+    );
+
+    let headers = "Content-Encoding: br\r\nContent-Length: 3\r\n";
+    let err = acquire_error(get_block(response("200 OK", headers, &[1, 2, 3]), &block).await);
+    assert!(matches!(err, AcquireError::Rejected(_)));
+    Ok(())
+}
@@ -0,0 +1,205 @@
+mod common;
+
+use anyhow::Result;
+use common::{serve_raw, FileServer};
+use futures::StreamExt;
+use incremental_file::{
+    acquirer::{AcquireError, Acquirer},
+    block::Block,
+    downloader::Downloader,
+    file::File,
+    storage::{MemoryStorage, Storage},
+};
+use incremental_file_http::acquirer::range::RangeAcquirer;
+
+#[tokio::test]
+async fn downloads_file_with_range_requests() -> Result<()> {
+    let data = (0..1000u32).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+    let file = File::from_data(&data, 64, &mut MemoryStorage::new()).await?;
+    let server = FileServer::start(data.clone()).await;
+    let acquirer = RangeAcquirer::new(server.url.clone(), &file)?;
+    let mut storage = MemoryStorage::new();
+    let report = Downloader::new(acquirer)
+        .with_concurrency(4)
+        .download(&file, &mut storage)
+        .await?;
+
+    assert!(report.is_complete());
+    assert_eq!(server.requests(), file.blocks.len());
+    assert_eq!(file.data(&storage).await?, data);
+    Ok(())
+}
+#[tokio::test]
+async fn unknown_block_is_not_found() -> Result<()> {
+    let data = (0..100).collect::<Vec<u8>>();
+    let file = File::from_data(&data, 10, &mut MemoryStorage::new()).await?;
+    let server = FileServer::start(data).await;
+    let acquirer = RangeAcquirer::new(server.url.clone(), &file)?;
+    let err = acquirer
+        .get_block(&Block::from_data([1, 2, 3]))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<AcquireError>(),
+        Some(AcquireError::NotFound(_))
+    ));
+    assert_eq!(server.requests(), 0);
+    Ok(())
+}
+#[tokio::test]
+async fn sparse_blocks_are_fetched_with_one_multi_range_request() -> Result<()> {
+    let data = (0..1000u32).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+    let file = File::from_data(&data, 64, &mut MemoryStorage::new()).await?;
+    let mut storage = MemoryStorage::new();
+    for (index, block) in file.blocks.iter().enumerate().step_by(3) {
+        let offset = index * 64;
+        let end = (offset + 64).min(data.len());
+        storage.upsert_block_data(block, &data[offset..end]).await?;
+    }
+    let server = FileServer::start(data.clone()).await;
+    let acquirer = RangeAcquirer::new(server.url.clone(), &file)?;
+    let report = Downloader::new(acquirer)
+        .with_batch_size(16)
+        .download(&file, &mut storage)
+        .await?;
+
+    assert!(report.is_complete());
+    assert_eq!(
+        report.fetched.len(),
+        file.blocks.len() - report.skipped.len()
+    );
+    assert_eq!(server.requests(), 1);
+    assert_eq!(file.data(&storage).await?, data);
+    Ok(())
+}
+#[tokio::test]
+async fn ranges_are_split_across_requests() -> Result<()> {
+    let data = (0..1000u32).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+    let file = File::from_data(&data, 10, &mut MemoryStorage::new()).await?;
+    let server = FileServer::start(data.clone()).await;
+    let acquirer = RangeAcquirer::new(server.url.clone(), &file)?.with_max_ranges(2);
+    // Every other block, so no two ranges can be merged
+    let blocks = file.blocks.iter().step_by(2).cloned().collect::<Vec<_>>();
+    let mut results = acquirer.get_blocks(&blocks).collect::<Vec<_>>().await;
+    results.sort_by_key(|(index, _)| *index);
+
+    assert_eq!(results.len(), blocks.len());
+    for (index, result) in results {
+        let offset = index * 20;
+        assert_eq!(result?, data[offset..offset + 10]);
+    }
+    assert_eq!(server.requests(), blocks.len().div_ceil(2));
+    Ok(())
+}
+#[tokio::test]
+async fn ranges_ending_at_the_largest_offset_are_rejected() -> Result<()> {
+    let file = File::from_data((0..20).collect::<Vec<u8>>(), 10, &mut MemoryStorage::new()).await?;
+    let last = u64::MAX;
+    let single = format!(
+        "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 0-{}/*\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        last
+    );
+    let body = format!(
+        "--frontier\r\nContent-Range: bytes {}-{}/*\r\n\r\n\r\n--frontier--\r\n",
+        last - 1,
+        last
+    );
+    let multipart = format!(
+        "HTTP/1.1 206 Partial Content\r\nContent-Type: multipart/byteranges; boundary=frontier\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+    for response in [single, multipart] {
+        let url = serve_raw(response.into_bytes()).await;
+        let acquirer = RangeAcquirer::new(url, &file)?;
+        let results = acquirer.get_blocks(&file.blocks).collect::<Vec<_>>().await;
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|(_, result)| result.is_err()));
+    }
+    Ok(())
+}
+#[tokio::test]
+async fn servers_ignoring_ranges_are_rejected() -> Result<()> {
+    let data = (0..20).collect::<Vec<u8>>();
+    let file = File::from_data(&data, 10, &mut MemoryStorage::new()).await?;
+    let mut response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        data.len()
+    )
+    .into_bytes();
+    response.extend_from_slice(&data);
+    let acquirer = RangeAcquirer::new(serve_raw(response).await, &file)?;
+
+    let err = acquirer.get_block(&file.blocks[1]).await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<AcquireError>(),
+        Some(AcquireError::Rejected(_))
+    ));
+    Ok(())
+}
+#[tokio::test]
+async fn partial_responses_must_match_the_requested_range() -> Result<()> {
+    let data = (0..20).collect::<Vec<u8>>();
+    let file = File::from_data(&data, 10, &mut MemoryStorage::new()).await?;
+    let partial = |range: &str, body: &[u8]| {
+        let mut response = format!(
+            "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}/20\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            range,
+            body.len()
+        )
+        .into_bytes();
+        response.extend_from_slice(body);
+        response
+    };
+    // The right length from the wrong offset, and the right offset with too much data
+    for response in [partial("0-9", &data[..10]), partial("10-19", &data)] {
+        let acquirer = RangeAcquirer::new(serve_raw(response).await, &file)?;
+        let err = acquirer.get_block(&file.blocks[1]).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<AcquireError>(),
+            Some(AcquireError::InvalidData(_))
+        ));
+    }
+    Ok(())
+}
+#[tokio::test]
+async fn batches_are_rejected_by_servers_ignoring_ranges() -> Result<()> {
+    let data = (0..20).collect::<Vec<u8>>();
+    let file = File::from_data(&data, 10, &mut MemoryStorage::new()).await?;
+    let mut response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        data.len()
+    )
+    .into_bytes();
+    response.extend_from_slice(&data);
+    let acquirer = RangeAcquirer::new(serve_raw(response).await, &file)?;
+
+    let results = acquirer.get_blocks(&file.blocks).collect::<Vec<_>>().await;
+    assert_eq!(results.len(), 2);
+    for (_, result) in results {
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<AcquireError>(),
+            Some(AcquireError::Rejected(_))
+        ));
+    }
+    Ok(())
+}
+#[tokio::test]
+async fn batches_stop_reading_after_the_requested_ranges() -> Result<()> {
+    let data = (0..20).collect::<Vec<u8>>();
+    let file = File::from_data(&data, 10, &mut MemoryStorage::new()).await?;
+    // Claims the requested range but keeps sending data after it
+    let mut response = format!(
+        "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 0-19/20\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        data.len() + 1
+    )
+    .into_bytes();
+    response.extend_from_slice(&data);
+    response.push(0);
+    let acquirer = RangeAcquirer::new(serve_raw(response).await, &file)?;
+
+    let results = acquirer.get_blocks(&file.blocks).collect::<Vec<_>>().await;
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|(_, result)| result.is_err()));
+    Ok(())
+}
@@ -11,7 +11,7 @@ pub async fn write_file<P: AsRef<Path>>(
     converter: &BoxedConverter,
 ) -> Result<()> {
     let path = path.as_ref();
-    let bytes = converter.serialize_file(&file)?;
+    let bytes = converter.serialize_file(file)?;
     tokio::fs::write(path, bytes).await?;
     Ok(())
 }
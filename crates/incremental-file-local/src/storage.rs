@@ -1,26 +1,34 @@
 use std::marker::Send;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use incremental_file::{block::Block, converter::Converter, file::File, storage::Storage};
 
 pub struct FileSystemStorage<C: Converter> {
-    root_dir: PathBuf,
     file_dir: PathBuf,
     block_dir: PathBuf,
     converter: C,
+    validate: bool,
 }
 
 impl<C: Converter> FileSystemStorage<C> {
     pub fn new(root_dir: PathBuf, converter: C) -> Self {
         Self {
-            root_dir: root_dir.clone(),
             file_dir: root_dir.join("files"),
             block_dir: root_dir.join("blocks"),
             converter,
+            validate: true,
         }
     }
+    /// Checks the length and hash of every block read, which is on by default. Storages wrapping
+    /// this one that store other bytes than the raw data of a block, like a compressed form of it,
+    /// need to turn it off.
+    pub fn with_validation(mut self, validate: bool) -> Self {
+        self.validate = validate;
+        self
+    }
     pub async fn ensure_dirs(&self) -> Result<()> {
         tokio::fs::create_dir_all(&self.file_dir).await?;
         tokio::fs::create_dir_all(&self.block_dir).await?;
@@ -28,6 +36,30 @@ impl<C: Converter> FileSystemStorage<C> {
     }
 }
 
+/// Numbers the temporary files of this process, so concurrent writes of the same path don't share one.
+static NEXT_WRITE: AtomicU64 = AtomicU64::new(0);
+
+/// Writes through a temporary file and renames it into place, so an interrupted write never
+/// leaves a partial file behind under the final name.
+async fn write_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        NEXT_WRITE.fetch_add(1, Ordering::Relaxed)
+    ));
+    let temp_path = path.with_file_name(temp_name);
+    if let Err(err) = tokio::fs::write(&temp_path, bytes).await {
+        let _ = tokio::fs::remove_file(&temp_path).await;
+        return Err(err.into());
+    }
+    if let Err(err) = tokio::fs::rename(&temp_path, path).await {
+        let _ = tokio::fs::remove_file(&temp_path).await;
+        return Err(err.into());
+    }
+    Ok(())
+}
+
 #[async_trait]
 impl<C: Converter> Storage for FileSystemStorage<C> {
     // Files
@@ -53,8 +85,8 @@ impl<C: Converter> Storage for FileSystemStorage<C> {
     async fn upsert_file(&mut self, file: &File) -> Result<()> {
         self.ensure_dirs().await?;
         let path = self.file_dir.join(&file.hash);
-        let bytes = self.converter.serialize_file(&file)?;
-        tokio::fs::write(path, bytes).await?;
+        let bytes = self.converter.serialize_file(file)?;
+        write_atomic(&path, &bytes).await?;
         Ok(())
     }
     async fn remove_file(&mut self, hash: &str) -> Result<()> {
@@ -71,6 +103,9 @@ impl<C: Converter> Storage for FileSystemStorage<C> {
         let exists = tokio::fs::metadata(&path).await.is_ok();
         if exists {
             let bytes = tokio::fs::read(&path).await?;
+            if !self.validate {
+                return Ok(Some(bytes));
+            }
             // Validate
             if bytes.len() as u64 != block.length {
                 return Err(anyhow::anyhow!(
@@ -104,8 +139,7 @@ impl<C: Converter> Storage for FileSystemStorage<C> {
         self.ensure_dirs().await?;
         let hash = &block.hash;
         let path = self.block_dir.join(hash);
-        let bytes = data.as_ref();
-        tokio::fs::write(path, bytes).await?;
+        write_atomic(&path, data.as_ref()).await?;
         Ok(())
     }
     async fn remove_block_data(&mut self, block: &Block) -> Result<()> {
@@ -0,0 +1,80 @@
+use anyhow::{anyhow, Result};
+
+/// One bit per block of a file, indexed by position in `File::blocks`. Bits are packed into bytes
+/// starting with the most significant bit, so the first block is the highest bit of the first byte.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bitfield {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl Bitfield {
+    /// A bitfield of `len` bits, none of them set.
+    pub fn new(len: usize) -> Self {
+        Self {
+            bytes: vec![0; len.div_ceil(8)],
+            len,
+        }
+    }
+    /// Reads a packed bitfield of `len` bits, refusing bytes of the wrong size or stray set bits
+    /// past the end.
+    pub fn from_bytes(bytes: Vec<u8>, len: usize) -> Result<Self> {
+        let bitfield = Self { bytes, len };
+        if bitfield.bytes.len() != len.div_ceil(8) {
+            return Err(anyhow!(
+                "Bitfield of {} bytes doesn't hold {} bits",
+                bitfield.bytes.len(),
+                len
+            ));
+        }
+        if (len..bitfield.bytes.len() * 8).any(|index| bitfield.bit(index)) {
+            return Err(anyhow!("Bitfield has bits set past its end"));
+        }
+        Ok(bitfield)
+    }
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, index: usize) -> bool {
+        index < self.len && self.bit(index)
+    }
+    pub fn set(&mut self, index: usize) {
+        if index < self.len {
+            self.bytes[index / 8] |= 0x80 >> (index % 8);
+        }
+    }
+    pub fn clear(&mut self, index: usize) {
+        if index < self.len {
+            self.bytes[index / 8] &= !(0x80 >> (index % 8));
+        }
+    }
+    /// Number of set bits.
+    pub fn count(&self) -> usize {
+        self.bytes
+            .iter()
+            .map(|byte| byte.count_ones() as usize)
+            .sum()
+    }
+    pub fn is_complete(&self) -> bool {
+        self.count() == self.len
+    }
+    /// Positions of the set bits.
+    pub fn ones(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.len).filter(|index| self.bit(*index))
+    }
+    /// Positions of the bits that aren't set.
+    pub fn zeros(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.len).filter(|index| !self.bit(*index))
+    }
+
+    fn bit(&self, index: usize) -> bool {
+        self.bytes[index / 8] & (0x80 >> (index % 8)) != 0
+    }
+}
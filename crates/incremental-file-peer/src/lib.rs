@@ -0,0 +1,3 @@
+pub mod bitfield;
+pub mod message;
+pub mod peer;
@@ -0,0 +1,135 @@
+//! Messages peers exchange about one file. Every message starts with its length as a big endian
+//! `u32`, not counting the length itself, followed by a type byte. Both peers open a connection
+//! with a `Handshake` and a `Bitfield`, and then send any message at any time.
+//!
+//! | Type | Message     | Payload                      |
+//! |------|-------------|------------------------------|
+//! | 1    | `Handshake` | block count `u32`, file hash |
+//! | 2    | `Bitfield`  | the packed bitfield          |
+//! | 3    | `Have`      | block index `u32`            |
+//! | 4    | `Request`   | block index `u32`            |
+//! | 5    | `Block`     | block index `u32`, data      |
+//! | 6    | `Reject`    | block index `u32`            |
+
+use anyhow::{anyhow, Context, Result};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Messages larger than this are refused, so a peer can't make the other side allocate at will.
+pub const MAX_MESSAGE_LENGTH: u32 = 64 * 1024 * 1024;
+
+const HANDSHAKE: u8 = 1;
+const BITFIELD: u8 = 2;
+const HAVE: u8 = 3;
+const REQUEST: u8 = 4;
+const BLOCK: u8 = 5;
+const REJECT: u8 = 6;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Handshake {
+        blocks: u32,
+        file_hash: String,
+    },
+    /// The packed bits of a `Bitfield`, the length is known from the handshake.
+    Bitfield(Vec<u8>),
+    Have(u32),
+    Request(u32),
+    Block(u32, Vec<u8>),
+    /// Answers a request for a block the peer doesn't have.
+    Reject(u32),
+}
+
+impl Message {
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        // Room for the length, filled in at the end
+        let mut buffer = vec![0; 4];
+        match self {
+            Message::Handshake { blocks, file_hash } => {
+                buffer.push(HANDSHAKE);
+                buffer.extend(blocks.to_be_bytes());
+                buffer.extend(file_hash.as_bytes());
+            }
+            Message::Bitfield(bytes) => {
+                buffer.push(BITFIELD);
+                buffer.extend(bytes);
+            }
+            Message::Have(index) => {
+                buffer.push(HAVE);
+                buffer.extend(index.to_be_bytes());
+            }
+            Message::Request(index) => {
+                buffer.push(REQUEST);
+                buffer.extend(index.to_be_bytes());
+            }
+            Message::Block(index, data) => {
+                buffer.push(BLOCK);
+                buffer.extend(index.to_be_bytes());
+                buffer.extend(data);
+            }
+            Message::Reject(index) => {
+                buffer.push(REJECT);
+                buffer.extend(index.to_be_bytes());
+            }
+        }
+        let length = u32::try_from(buffer.len() - 4)
+            .ok()
+            .filter(|length| *length <= MAX_MESSAGE_LENGTH)
+            .ok_or_else(|| anyhow!("Message is larger than {} bytes", MAX_MESSAGE_LENGTH))?;
+        buffer[..4].copy_from_slice(&length.to_be_bytes());
+        Ok(buffer)
+    }
+
+    /// Decodes a message without its length prefix.
+    pub fn decode(bytes: &[u8]) -> Result<Message> {
+        let (message_type, payload) = bytes.split_first().context("Empty message")?;
+        let index = || -> Result<u32> {
+            let index = payload.get(..4).context("Message is truncated")?;
+            Ok(u32::from_be_bytes(index.try_into()?))
+        };
+        let exact = |message: Message| {
+            if payload.len() == 4 {
+                Ok(message)
+            } else {
+                Err(anyhow!("Message has {} payload bytes", payload.len()))
+            }
+        };
+        match *message_type {
+            HANDSHAKE => Ok(Message::Handshake {
+                blocks: index()?,
+                file_hash: String::from_utf8(payload[4..].to_vec())?,
+            }),
+            BITFIELD => Ok(Message::Bitfield(payload.to_vec())),
+            HAVE => exact(Message::Have(index()?)),
+            REQUEST => exact(Message::Request(index()?)),
+            BLOCK => Ok(Message::Block(index()?, payload[4..].to_vec())),
+            REJECT => exact(Message::Reject(index()?)),
+            message_type => Err(anyhow!("Unknown message type {}", message_type)),
+        }
+    }
+}
+
+/// Reads the next message, or `None` if the stream ended between messages.
+pub async fn read_message<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<Message>> {
+    let mut length = [0; 4];
+    match reader.read_exact(&mut length).await {
+        Ok(_) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    }
+    let length = u32::from_be_bytes(length);
+    if length > MAX_MESSAGE_LENGTH {
+        return Err(anyhow!("Message of {} bytes is too large", length));
+    }
+    let mut bytes = vec![0; length as usize];
+    reader
+        .read_exact(&mut bytes)
+        .await
+        .context("Stream ended inside a message")?;
+    Message::decode(&bytes).map(Some)
+}
+
+pub async fn write_message<W: AsyncWrite + Unpin>(writer: &mut W, message: &Message) -> Result<()> {
+    writer.write_all(&message.encode()?).await?;
+    writer.flush().await?;
+    Ok(())
+}
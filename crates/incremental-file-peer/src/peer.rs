@@ -0,0 +1,309 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use incremental_file::{file::File, storage::Storage};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::{mpsc, watch, RwLock},
+    time::Instant,
+};
+
+use crate::{
+    bitfield::Bitfield,
+    message::{read_message, write_message, Message},
+};
+
+/// Requests a connection keeps open at once, so one slow peer can't hold on to many blocks.
+const MAX_OUTSTANDING: usize = 8;
+/// Messages received from a peer that wait to be handled. Once they pile up the connection isn't
+/// read anymore, so a peer flooding requests is held back by its own connection.
+const MAX_QUEUED: usize = 64;
+/// Pause after a failed accept, so a listener that keeps failing doesn't spin.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// One participant in the exchange of a file. A peer serves the blocks it holds to every peer it
+/// is connected to and requests the blocks it is missing from them, so a group of peers can finish
+/// a file together while only some of them talk to the origin. Every received block is checked
+/// with `Block::validate` before it is stored, and a peer sending invalid data or leaving a
+/// request unanswered for too long is disconnected.
+pub struct Peer<S: Storage> {
+    file: File,
+    storage: RwLock<S>,
+    have: watch::Sender<Bitfield>,
+    /// Blocks requested from some connection, so no two connections ask for the same block.
+    in_flight: Mutex<HashSet<usize>>,
+    /// Signalled when blocks are taken out of `in_flight` without being stored, so the other
+    /// connections can request them instead.
+    released: watch::Sender<()>,
+    request_timeout: Duration,
+}
+
+impl<S: Storage + 'static> Peer<S> {
+    /// Starts out with the blocks of the file the storage already holds.
+    pub async fn new(file: File, storage: S) -> Result<Self> {
+        let mut have = Bitfield::new(file.blocks.len());
+        for (index, block) in file.blocks.iter().enumerate() {
+            if storage.block_exists(block).await? {
+                have.set(index);
+            }
+        }
+        Ok(Self {
+            file,
+            storage: RwLock::new(storage),
+            have: watch::channel(have).0,
+            in_flight: Mutex::new(HashSet::new()),
+            released: watch::channel(()).0,
+            request_timeout: Duration::from_secs(30),
+        })
+    }
+    /// Sets how long a connected peer may take to answer a request before it is disconnected, 30
+    /// seconds by default.
+    pub fn with_request_timeout(mut self, request_timeout: Duration) -> Self {
+        self.request_timeout = request_timeout;
+        self
+    }
+    pub fn request_timeout(&self) -> Duration {
+        self.request_timeout
+    }
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+    pub fn storage(&self) -> &RwLock<S> {
+        &self.storage
+    }
+    /// The blocks this peer holds.
+    pub fn have(&self) -> Bitfield {
+        self.have.borrow().clone()
+    }
+    pub fn is_complete(&self) -> bool {
+        self.have.borrow().is_complete()
+    }
+    /// Resolves once the peer holds every block of the file.
+    pub async fn wait_complete(&self) {
+        let mut have = self.have.subscribe();
+        // The sender lives as long as the peer, so this can't fail
+        let _ = have.wait_for(|have| have.is_complete()).await;
+    }
+
+    /// Exchanges blocks with every peer that connects, for as long as the peer runs. A connection
+    /// that fails while being accepted is logged and skipped.
+    pub async fn listen(self: Arc<Self>, listener: TcpListener) -> Result<()> {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(err) => {
+                    log::warn!("Failed to accept a peer: {}", err);
+                    tokio::time::sleep(ACCEPT_BACKOFF).await;
+                    continue;
+                }
+            };
+            if let Err(err) = stream.set_nodelay(true) {
+                log::warn!("Failed to configure a peer connection: {}", err);
+                continue;
+            }
+            tokio::spawn(self.clone().exchange(stream));
+        }
+    }
+    /// Connects to a peer and exchanges blocks with it until either side disconnects.
+    pub async fn connect<A: ToSocketAddrs>(self: Arc<Self>, address: A) -> Result<()> {
+        let stream = TcpStream::connect(address).await?;
+        stream.set_nodelay(true)?;
+        self.exchange(stream).await
+    }
+    /// Exchanges blocks with the peer on the other end of any established connection.
+    pub async fn exchange<T: AsyncRead + AsyncWrite + Send + 'static>(
+        self: Arc<Self>,
+        stream: T,
+    ) -> Result<()> {
+        let (mut reader, mut writer) = tokio::io::split(stream);
+        let mut have = self.have.subscribe();
+        let mut announced = have.borrow_and_update().clone();
+        let handshake = Message::Handshake {
+            blocks: self.file.blocks.len() as u32,
+            file_hash: self.file.hash.clone(),
+        };
+        write_message(&mut writer, &handshake).await?;
+        write_message(
+            &mut writer,
+            &Message::Bitfield(announced.as_bytes().to_vec()),
+        )
+        .await?;
+        if read_message(&mut reader).await? != Some(handshake) {
+            return Err(anyhow!("Peer is exchanging another file"));
+        }
+        let mut remote = match read_message(&mut reader).await? {
+            Some(Message::Bitfield(bytes)) => Bitfield::from_bytes(bytes, self.file.blocks.len())?,
+            _ => return Err(anyhow!("Peer didn't send its bitfield")),
+        };
+
+        // Reading runs on its own, so a half read message is never dropped by `select!` and both
+        // peers can't get stuck writing to each other
+        let (messages, mut incoming) = mpsc::channel(MAX_QUEUED);
+        let reading = tokio::spawn(async move {
+            loop {
+                let message = read_message(&mut reader).await.transpose();
+                let end = !matches!(message, Some(Ok(_)));
+                if messages.send(message).await.is_err() || end {
+                    return;
+                }
+            }
+        });
+        let mut released = self.released.subscribe();
+        // Requests sent on this connection, with the time they have to be answered by
+        let mut outstanding = HashMap::new();
+        let result = async {
+            loop {
+                self.request_missing(&remote, &mut outstanding, &mut writer)
+                    .await?;
+                let deadline = outstanding.values().min().copied();
+                tokio::select! {
+                    message = incoming.recv() => match message.flatten() {
+                        Some(message) => {
+                            self.handle(message?, &mut remote, &mut outstanding, &mut writer)
+                                .await?
+                        }
+                        None => return Ok(()),
+                    },
+                    Ok(()) = have.changed() => {
+                        let now = have.borrow_and_update().clone();
+                        for index in now.ones().filter(|index| !announced.get(*index)) {
+                            write_message(&mut writer, &Message::Have(index as u32)).await?;
+                        }
+                        announced = now;
+                    }
+                    // Another connection gave up blocks, which may be requested here now
+                    Ok(()) = released.changed() => {}
+                    _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)),
+                        if deadline.is_some() =>
+                    {
+                        return Err(anyhow!(
+                            "Peer didn't answer a request within {:?}",
+                            self.request_timeout
+                        ));
+                    }
+                }
+            }
+        }
+        .await;
+
+        reading.abort();
+        self.release(outstanding.into_keys());
+        result
+    }
+
+    /// Takes blocks out of `in_flight` and lets the other connections know about those still missing.
+    fn release(&self, indices: impl IntoIterator<Item = usize>) {
+        let missing = {
+            let have = self.have.borrow();
+            let mut in_flight = self.in_flight.lock().unwrap();
+            indices
+                .into_iter()
+                .filter(|index| in_flight.remove(index) && !have.get(*index))
+                .count()
+        };
+        if missing > 0 {
+            self.released.send_replace(());
+        }
+    }
+
+    /// Requests blocks the remote peer holds and nobody else is fetching yet, lowest index first.
+    async fn request_missing<W: AsyncWrite + Unpin>(
+        &self,
+        remote: &Bitfield,
+        outstanding: &mut HashMap<usize, Instant>,
+        writer: &mut W,
+    ) -> Result<()> {
+        while outstanding.len() < MAX_OUTSTANDING {
+            let next = {
+                let have = self.have.borrow();
+                let mut in_flight = self.in_flight.lock().unwrap();
+                let next = remote
+                    .ones()
+                    .find(|index| !have.get(*index) && !in_flight.contains(index));
+                if let Some(index) = next {
+                    in_flight.insert(index);
+                }
+                next
+            };
+            let Some(index) = next else {
+                return Ok(());
+            };
+            outstanding.insert(index, Instant::now() + self.request_timeout);
+            write_message(writer, &Message::Request(index as u32)).await?;
+        }
+        Ok(())
+    }
+
+    /// Validates and stores a block received from a peer.
+    async fn store(&self, index: usize, data: Vec<u8>) -> Result<()> {
+        let block = &self.file.blocks[index];
+        block.validate(&data)?;
+        if !self.have.borrow().get(index) {
+            self.storage
+                .write()
+                .await
+                .upsert_block_data(block, data)
+                .await?;
+            // Blocks with the same hash are stored only once
+            self.have.send_modify(|have| {
+                for (index, other) in self.file.blocks.iter().enumerate() {
+                    if other.hash == block.hash {
+                        have.set(index);
+                    }
+                }
+            });
+        }
+        Ok(())
+    }
+
+    async fn handle<W: AsyncWrite + Unpin>(
+        &self,
+        message: Message,
+        remote: &mut Bitfield,
+        outstanding: &mut HashMap<usize, Instant>,
+        writer: &mut W,
+    ) -> Result<()> {
+        match message {
+            Message::Have(index) => remote.set(index as usize),
+            Message::Request(index) => {
+                let index = index as usize;
+                let data = match self.file.blocks.get(index) {
+                    Some(block) if self.have.borrow().get(index) => {
+                        self.storage.read().await.get_block_data(block).await?
+                    }
+                    _ => None,
+                };
+                let response = match data {
+                    Some(data) => Message::Block(index as u32, data),
+                    None => Message::Reject(index as u32),
+                };
+                write_message(writer, &response).await?;
+            }
+            Message::Block(index, data) => {
+                let index = index as usize;
+                if outstanding.remove(&index).is_none() {
+                    return Err(anyhow!("Peer sent block {} without a request", index));
+                }
+                let stored = self.store(index, data).await;
+                self.release([index]);
+                stored?;
+            }
+            Message::Reject(index) => {
+                let index = index as usize;
+                if outstanding.remove(&index).is_some() {
+                    remote.clear(index);
+                    self.release([index]);
+                }
+            }
+            Message::Handshake { .. } | Message::Bitfield(_) => {
+                return Err(anyhow!("Peer repeated its handshake"));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,236 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{Context, Result};
+use incremental_file::{
+    file::File,
+    storage::{MemoryStorage, Storage},
+};
+use incremental_file_peer::{
+    bitfield::Bitfield,
+    message::{read_message, write_message, Message},
+    peer::Peer,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, DuplexStream},
+    net::{TcpListener, TcpStream},
+};
+
+/// A file of 20 blocks, and an origin holding all of them.
+async fn origin() -> Result<(Vec<u8>, File, MemoryStorage)> {
+    let data = (0..200).collect::<Vec<u8>>();
+    let mut storage = MemoryStorage::new();
+    let file = File::from_data(&data, 10, &mut storage).await?;
+    Ok((data, file, storage))
+}
+
+/// A peer holding the blocks of the origin whose index passes the filter.
+async fn peer(
+    file: &File,
+    origin: &MemoryStorage,
+    filter: impl Fn(usize) -> bool,
+) -> Result<Arc<Peer<MemoryStorage>>> {
+    let mut storage = MemoryStorage::new();
+    for (_, block) in file
+        .blocks
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| filter(*index))
+    {
+        let data = origin
+            .get_block_data(block)
+            .await?
+            .context("Block doesn't exist")?;
+        storage.upsert_block_data(block, data).await?;
+    }
+    Ok(Arc::new(Peer::new(file.clone(), storage).await?))
+}
+
+async fn listen(peer: &Arc<Peer<MemoryStorage>>) -> Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?.to_string();
+    tokio::spawn(peer.clone().listen(listener));
+    Ok(address)
+}
+
+async fn wait_complete(peers: &[&Arc<Peer<MemoryStorage>>]) -> Result<()> {
+    for peer in peers {
+        tokio::time::timeout(Duration::from_secs(10), peer.wait_complete()).await?;
+    }
+    Ok(())
+}
+
+/// Claims every block of the file on the stream, then waits for the peer on the other end to
+/// request as many blocks as it will at once, without answering any of them.
+async fn claim_all<T: AsyncRead + AsyncWrite + Unpin>(file: &File, stream: &mut T) -> Result<()> {
+    let blocks = file.blocks.len();
+    let mut all = Bitfield::new(blocks);
+    (0..blocks).for_each(|index| all.set(index));
+    let handshake = Message::Handshake {
+        blocks: blocks as u32,
+        file_hash: file.hash.clone(),
+    };
+    write_message(stream, &handshake).await?;
+    write_message(stream, &Message::Bitfield(all.as_bytes().to_vec())).await?;
+    let mut requests = 0;
+    while requests < 8 {
+        match read_message(stream).await? {
+            Some(Message::Request(_)) => requests += 1,
+            Some(_) => {}
+            None => anyhow::bail!("Peer disconnected"),
+        }
+    }
+    Ok(())
+}
+
+/// Connects the peer to a fake one that claims every block and never answers a request.
+async fn connect_silent(peer: &Arc<Peer<MemoryStorage>>) -> Result<DuplexStream> {
+    let (stream, mut silent) = tokio::io::duplex(1 << 16);
+    tokio::spawn(peer.clone().exchange(stream));
+    claim_all(peer.file(), &mut silent).await?;
+    Ok(silent)
+}
+
+#[test]
+fn bitfield_packs_bits() -> Result<()> {
+    let mut bitfield = Bitfield::new(10);
+    bitfield.set(0);
+    bitfield.set(9);
+    bitfield.set(10);
+    assert_eq!(bitfield.as_bytes(), [0b1000_0000, 0b0100_0000]);
+    assert_eq!(bitfield.count(), 2);
+    assert_eq!(bitfield.ones().collect::<Vec<_>>(), [0, 9]);
+    bitfield.clear(0);
+    assert!(!bitfield.get(0));
+
+    let parsed = Bitfield::from_bytes(vec![0xff, 0b1100_0000], 10)?;
+    assert!(parsed.is_complete());
+    assert!(Bitfield::from_bytes(vec![0xff, 0b1110_0000], 10).is_err());
+    assert!(Bitfield::from_bytes(vec![0xff], 10).is_err());
+    Ok(())
+}
+#[tokio::test]
+async fn peers_complete_file_together() -> Result<()> {
+    let (data, file, origin) = origin().await?;
+    let even = peer(&file, &origin, |index| index % 2 == 0).await?;
+    let odd = peer(&file, &origin, |index| index % 2 == 1).await?;
+    let empty = peer(&file, &origin, |_| false).await?;
+    assert_eq!(even.have().count(), 10);
+
+    let even_address = listen(&even).await?;
+    let odd_address = listen(&odd).await?;
+    tokio::spawn(odd.clone().connect(even_address.clone()));
+    tokio::spawn(empty.clone().connect(even_address));
+    tokio::spawn(empty.clone().connect(odd_address));
+
+    wait_complete(&[&even, &odd, &empty]).await?;
+    for peer in [&even, &odd, &empty] {
+        assert_eq!(file.data(&*peer.storage().read().await).await?, data);
+    }
+    Ok(())
+}
+#[tokio::test]
+async fn blocks_are_relayed_along_a_chain() -> Result<()> {
+    let (data, file, origin) = origin().await?;
+    let seed = peer(&file, &origin, |_| true).await?;
+    let middle = peer(&file, &origin, |_| false).await?;
+    let last = peer(&file, &origin, |_| false).await?;
+
+    // The last peer only knows the middle one, which announces blocks as it gets them
+    let seed_address = listen(&seed).await?;
+    let middle_address = listen(&middle).await?;
+    tokio::spawn(middle.clone().connect(seed_address));
+    tokio::spawn(last.clone().connect(middle_address));
+
+    wait_complete(&[&middle, &last]).await?;
+    assert_eq!(file.data(&*last.storage().read().await).await?, data);
+    Ok(())
+}
+#[tokio::test]
+async fn peers_of_other_files_are_refused() -> Result<()> {
+    let (_, file, origin) = origin().await?;
+    let seed = peer(&file, &origin, |_| true).await?;
+    let other_file = File::from_data([1, 2, 3], 10, &mut MemoryStorage::new()).await?;
+    let other = Arc::new(Peer::new(other_file, MemoryStorage::new()).await?);
+
+    let address = listen(&seed).await?;
+    assert!(other.connect(address).await.is_err());
+    Ok(())
+}
+#[tokio::test]
+async fn invalid_blocks_are_not_stored() -> Result<()> {
+    let (_, file, origin) = origin().await?;
+    let honest = peer(&file, &origin, |_| false).await?;
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?;
+
+    // Claims to have every block and answers every request with garbage
+    let blocks = file.blocks.len();
+    let handshake = Message::Handshake {
+        blocks: blocks as u32,
+        file_hash: file.hash.clone(),
+    };
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await?;
+        let mut all = Bitfield::new(blocks);
+        (0..blocks).for_each(|index| all.set(index));
+        write_message(&mut stream, &handshake).await?;
+        write_message(&mut stream, &Message::Bitfield(all.as_bytes().to_vec())).await?;
+        while let Some(message) = read_message(&mut stream).await? {
+            if let Message::Request(index) = message {
+                write_message(&mut stream, &Message::Block(index, vec![0; 10])).await?;
+            }
+        }
+        anyhow::Ok(())
+    });
+
+    let stream = TcpStream::connect(address).await?;
+    assert!(honest.clone().exchange(stream).await.is_err());
+    assert_eq!(honest.have().count(), 0);
+    Ok(())
+}
+#[tokio::test]
+async fn blocks_of_a_disconnected_peer_are_requested_elsewhere() -> Result<()> {
+    let (data, file, origin) = origin().await?;
+    let seed = peer(&file, &origin, |_| true).await?;
+    let empty = peer(&file, &origin, |_| false).await?;
+    let silent = connect_silent(&empty).await?;
+
+    // The seed serves everything nobody else is fetching, then the silent peer goes away
+    tokio::spawn(empty.clone().connect(listen(&seed).await?));
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while empty.have().count() < 12 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await?;
+    drop(silent);
+
+    wait_complete(&[&empty]).await?;
+    assert_eq!(file.data(&*empty.storage().read().await).await?, data);
+    Ok(())
+}
+#[tokio::test]
+async fn unanswered_requests_time_out() -> Result<()> {
+    let (data, file, origin) = origin().await?;
+    let seed = peer(&file, &origin, |_| true).await?;
+    let storage = MemoryStorage::new();
+    let empty = Arc::new(
+        Peer::new(file.clone(), storage)
+            .await?
+            .with_request_timeout(Duration::from_millis(100)),
+    );
+    assert_eq!(empty.request_timeout(), Duration::from_millis(100));
+
+    let (stream, mut silent) = tokio::io::duplex(1 << 16);
+    let exchange = tokio::spawn(empty.clone().exchange(stream));
+    claim_all(&file, &mut silent).await?;
+    tokio::spawn(empty.clone().connect(listen(&seed).await?));
+
+    // The silent peer is dropped while still connected, and the seed serves its blocks instead
+    let result = tokio::time::timeout(Duration::from_secs(10), exchange).await??;
+    assert!(result.is_err());
+    wait_complete(&[&empty]).await?;
+    assert_eq!(file.data(&*empty.storage().read().await).await?, data);
+    drop(silent);
+    Ok(())
+}
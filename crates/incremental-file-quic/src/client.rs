@@ -0,0 +1,97 @@
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::{stream::BoxStream, StreamExt};
+use incremental_file::{
+    acquirer::{AcquireError, Acquirer},
+    block::Block,
+    file::File,
+};
+use incremental_file_tcp::frame::{read_frame, write_frame, Frame};
+use quinn::{ClientConfig, Connection, Endpoint};
+
+/// Requests blocks and files from a `QuicServer`, using the frames of `incremental-file-tcp`. Every
+/// request travels on a stream of its own within one connection, so a packet lost on the way only
+/// holds up the request it belongs to, while the others go on.
+///
+/// A lost connection fails all requests with `AcquireError::Unavailable`, connect again to go on.
+pub struct QuicAcquirer {
+    connection: Connection,
+}
+
+impl QuicAcquirer {
+    /// Connects to a server whose certificate is valid for `server_name` according to `config`,
+    /// see `config::client_config`.
+    pub async fn connect(
+        address: SocketAddr,
+        server_name: &str,
+        config: ClientConfig,
+    ) -> Result<Self> {
+        let local = match address {
+            SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+            SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+        };
+        let endpoint = Endpoint::client(local)?;
+        let connection = endpoint.connect_with(config, address, server_name)?.await?;
+        Ok(Self::new(connection))
+    }
+    /// Speaks the protocol over an established connection, which keeps its endpoint running.
+    pub fn new(connection: Connection) -> Self {
+        Self { connection }
+    }
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// Requests a file by hash, `None` if the server doesn't have it.
+    pub async fn get_file(&self, hash: &str) -> Result<Option<File>> {
+        let response = self
+            .request(&Frame::GetFile {
+                id: 0,
+                hash: hash.to_string(),
+            })
+            .await?;
+        response.into_file(hash)
+    }
+
+    /// Sends a request on a new stream and reads the response from it. Streams are reset when
+    /// dropped, so a cancelled request doesn't keep the server busy.
+    async fn request(&self, frame: &Frame) -> Result<Frame> {
+        let (mut send, mut receive) = self.connection.open_bi().await.map_err(unavailable)?;
+        write_frame(&mut send, frame).await.map_err(unavailable)?;
+        send.finish().map_err(unavailable)?;
+        match read_frame(&mut receive).await {
+            Ok(Some(response)) => Ok(response),
+            Ok(None) => {
+                Err(AcquireError::Unavailable("Stream ended without a response".to_string()).into())
+            }
+            Err(err) => Err(unavailable(err).into()),
+        }
+    }
+}
+
+fn unavailable<E: std::fmt::Display>(err: E) -> AcquireError {
+    AcquireError::Unavailable(err.to_string())
+}
+
+#[async_trait]
+impl Acquirer for QuicAcquirer {
+    async fn get_block(&self, block: &Block) -> Result<Vec<u8>> {
+        let response = self
+            .request(&Frame::GetBlock {
+                id: 0,
+                block: block.clone(),
+            })
+            .await?;
+        response.into_block_data(block)
+    }
+    /// Opens a stream for every block right away and yields the blocks as they arrive. The
+    /// server limits how many streams are open at once, further requests wait for a free one.
+    fn get_blocks<'a>(&'a self, blocks: &'a [Block]) -> BoxStream<'a, (usize, Result<Vec<u8>>)> {
+        futures::stream::iter(blocks.iter().enumerate())
+            .map(move |(index, block)| async move { (index, self.get_block(block).await) })
+            .buffer_unordered(blocks.len().max(1))
+            .boxed()
+    }
+}
@@ -0,0 +1,37 @@
+//! TLS configuration of both ends. QUIC always runs over TLS, so a server needs a certificate and
+//! clients need to trust it. Deployments bring certificates of their own through quinn's
+//! `ServerConfig::with_single_cert`, `self_signed` covers tests and closed networks where clients
+//! are handed the certificate of the server.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use quinn::{
+    rustls::{
+        pki_types::{CertificateDer, PrivatePkcs8KeyDer},
+        RootCertStore,
+    },
+    ClientConfig, ServerConfig,
+};
+
+/// A server configuration with a freshly generated certificate for the given names, along with the
+/// certificate for clients to trust.
+pub fn self_signed(names: Vec<String>) -> Result<(ServerConfig, CertificateDer<'static>)> {
+    let certified = rcgen::generate_simple_self_signed(names)?;
+    let certificate = certified.cert.der().clone();
+    let key = PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der());
+    let config = ServerConfig::with_single_cert(vec![certificate.clone()], key.into())?;
+    Ok((config, certificate))
+}
+
+/// A client configuration trusting only the given certificates, either self-signed server
+/// certificates or the certificate authorities that issued them.
+pub fn client_config<I: IntoIterator<Item = CertificateDer<'static>>>(
+    certificates: I,
+) -> Result<ClientConfig> {
+    let mut roots = RootCertStore::empty();
+    for certificate in certificates {
+        roots.add(certificate)?;
+    }
+    Ok(ClientConfig::with_root_certificates(Arc::new(roots))?)
+}
@@ -0,0 +1,63 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use incremental_file::storage::Storage;
+use incremental_file_tcp::{frame::read_frame, server::respond};
+use quinn::{Connection, ConnectionError, Endpoint, RecvStream, SendStream};
+
+/// Serves the blocks and files of a storage to `QuicAcquirer`s, answering every stream a client
+/// opens with one response. Streams are handled concurrently, up to the number of streams the
+/// endpoint lets a client open at once.
+pub struct QuicServer<S: Storage> {
+    storage: S,
+}
+
+impl<S: Storage + 'static> QuicServer<S> {
+    pub fn new(storage: S) -> Self {
+        Self { storage }
+    }
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    /// Accepts connections until the endpoint is closed. Connections failing their handshake are
+    /// left out without stopping the server.
+    pub async fn serve(self: Arc<Self>, endpoint: Endpoint) -> Result<()> {
+        while let Some(incoming) = endpoint.accept().await {
+            let server = self.clone();
+            tokio::spawn(async move {
+                let connection = incoming.await?;
+                server.serve_connection(connection).await
+            });
+        }
+        Ok(())
+    }
+    /// Answers the streams of an established connection until the client closes it.
+    pub async fn serve_connection(self: Arc<Self>, connection: Connection) -> Result<()> {
+        loop {
+            let (send, receive) = match connection.accept_bi().await {
+                Ok(streams) => streams,
+                Err(ConnectionError::ApplicationClosed(_) | ConnectionError::LocallyClosed) => {
+                    return Ok(())
+                }
+                Err(err) => return Err(err.into()),
+            };
+            tokio::spawn(self.clone().serve_stream(send, receive));
+        }
+    }
+
+    /// A malformed request ends the stream without a response, the other streams of the
+    /// connection go on.
+    async fn serve_stream(
+        self: Arc<Self>,
+        mut send: SendStream,
+        mut receive: RecvStream,
+    ) -> Result<()> {
+        if let Some(request) = read_frame(&mut receive).await? {
+            let response = respond(&self.storage, request).await?;
+            send.write_all(&response).await?;
+            send.finish()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,113 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use anyhow::Result;
+use futures::StreamExt;
+use incremental_file::{
+    acquirer::{AcquireError, Acquirer},
+    block::Block,
+    downloader::Downloader,
+    file::File,
+    storage::{MemoryStorage, Storage},
+};
+use incremental_file_quic::{
+    client::QuicAcquirer,
+    config::{client_config, self_signed},
+    server::QuicServer,
+};
+use quinn::{rustls::pki_types::CertificateDer, Endpoint};
+
+/// Serves the storage on loopback, returning the address and the certificate to trust.
+async fn start(storage: MemoryStorage) -> Result<(SocketAddr, CertificateDer<'static>)> {
+    let (config, certificate) = self_signed(vec!["localhost".to_string()])?;
+    let endpoint = Endpoint::server(config, "127.0.0.1:0".parse()?)?;
+    let address = endpoint.local_addr()?;
+    tokio::spawn(Arc::new(QuicServer::new(storage)).serve(endpoint));
+    Ok((address, certificate))
+}
+
+async fn connect(
+    address: SocketAddr,
+    certificate: CertificateDer<'static>,
+) -> Result<QuicAcquirer> {
+    QuicAcquirer::connect(address, "localhost", client_config([certificate])?).await
+}
+
+async fn origin(data: &[u8]) -> Result<(MemoryStorage, File)> {
+    let mut storage = MemoryStorage::new();
+    let file = File::from_data(data, 10, &mut storage).await?;
+    storage.upsert_file(&file).await?;
+    Ok((storage, file))
+}
+
+#[tokio::test]
+async fn downloads_file_over_loopback() -> Result<()> {
+    let data = (0..255).collect::<Vec<u8>>();
+    let (origin, file) = origin(&data).await?;
+    let (address, certificate) = start(origin).await?;
+    let acquirer = connect(address, certificate).await?;
+
+    let fetched = acquirer.get_file(&file.hash).await?.expect("File exists");
+    assert_eq!(fetched.hash, file.hash);
+    let mut storage = MemoryStorage::new();
+    let report = Downloader::new(acquirer)
+        .with_concurrency(4)
+        .with_batch_size(8)
+        .download(&fetched, &mut storage)
+        .await?;
+    assert!(report.is_complete());
+    assert_eq!(file.data(&storage).await?, data);
+    Ok(())
+}
+#[tokio::test]
+async fn more_requests_than_streams_at_once() -> Result<()> {
+    // 300 blocks, while the server lets a client open 100 streams at once
+    let data = (0..3000)
+        .map(|byte| (byte % 251) as u8)
+        .collect::<Vec<u8>>();
+    let (origin, file) = origin(&data).await?;
+    let (address, certificate) = start(origin).await?;
+    let acquirer = connect(address, certificate).await?;
+
+    let mut results = acquirer.get_blocks(&file.blocks).collect::<Vec<_>>().await;
+    results.sort_by_key(|(index, _)| *index);
+    assert_eq!(results.len(), file.blocks.len());
+    for ((_, result), chunk) in results.into_iter().zip(data.chunks(10)) {
+        assert_eq!(result?, chunk);
+    }
+    Ok(())
+}
+#[tokio::test]
+async fn missing_blocks_and_files_are_not_found() -> Result<()> {
+    let (address, certificate) = start(MemoryStorage::new()).await?;
+    let acquirer = connect(address, certificate).await?;
+    let block = Block::from_data([1, 2, 3]);
+    let err = acquirer.get_block(&block).await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<AcquireError>(),
+        Some(AcquireError::NotFound(hash)) if *hash == block.hash
+    ));
+    assert!(acquirer.get_file("0123").await?.is_none());
+    Ok(())
+}
+#[tokio::test]
+async fn untrusted_certificates_are_refused() -> Result<()> {
+    let (address, _) = start(MemoryStorage::new()).await?;
+    let (_, other) = self_signed(vec!["localhost".to_string()])?;
+    assert!(connect(address, other).await.is_err());
+    Ok(())
+}
+#[tokio::test]
+async fn lost_connection_is_unavailable() -> Result<()> {
+    let (address, certificate) = start(MemoryStorage::new()).await?;
+    let acquirer = connect(address, certificate).await?;
+    acquirer.connection().close(0u32.into(), b"");
+    let err = acquirer
+        .get_block(&Block::from_data([1, 2, 3]))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<AcquireError>(),
+        Some(AcquireError::Unavailable(_))
+    ));
+    Ok(())
+}
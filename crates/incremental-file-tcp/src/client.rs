@@ -0,0 +1,146 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::{stream::BoxStream, StreamExt};
+use incremental_file::{
+    acquirer::{AcquireError, Acquirer},
+    block::Block,
+    file::File,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
+    net::{TcpStream, ToSocketAddrs},
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+
+use crate::frame::{read_frame, Frame};
+
+/// Requests that are waiting for their response, `None` once the connection is gone.
+type Pending = Mutex<Option<HashMap<u32, oneshot::Sender<Frame>>>>;
+
+/// Requests blocks and files from a `StreamServer` over a single connection. Requests are
+/// pipelined: any number of them can be in flight at once and are answered in any order, so
+/// concurrent downloads and batches don't wait for each other's round trips.
+///
+/// A lost connection fails all requests with `AcquireError::Unavailable`, connect again to go on.
+pub struct StreamAcquirer {
+    /// Encoded frames for the writer task, so a cancelled request never leaves half a frame behind.
+    outgoing: mpsc::UnboundedSender<Vec<u8>>,
+    pending: Arc<Pending>,
+    next_id: AtomicU32,
+    tasks: [JoinHandle<()>; 2],
+}
+
+impl StreamAcquirer {
+    pub async fn connect<A: ToSocketAddrs>(address: A) -> Result<Self> {
+        let stream = TcpStream::connect(address).await?;
+        stream.set_nodelay(true)?;
+        Ok(Self::new(stream))
+    }
+    /// Connects to a server on a Unix domain socket, typically a local daemon owning the storage.
+    #[cfg(unix)]
+    pub async fn connect_unix<P: AsRef<std::path::Path>>(path: P) -> Result<Self> {
+        Ok(Self::new(tokio::net::UnixStream::connect(path).await?))
+    }
+    /// Speaks the protocol over any established connection.
+    pub fn new<T: AsyncRead + AsyncWrite + Send + 'static>(stream: T) -> Self {
+        let (mut reader, mut writer) = tokio::io::split(stream);
+        let pending: Arc<Pending> = Arc::new(Mutex::new(Some(HashMap::new())));
+        let dispatch = pending.clone();
+        let reader = tokio::spawn(async move {
+            while let Ok(Some(frame)) = read_frame(&mut reader).await {
+                let sender = match dispatch.lock().unwrap().as_mut() {
+                    Some(pending) => pending.remove(&frame.id()),
+                    None => None,
+                };
+                // The caller may have given up on the request in the meantime
+                if let Some(sender) = sender {
+                    let _ = sender.send(frame);
+                }
+            }
+            // Dropping the senders fails every request that is still waiting
+            dispatch.lock().unwrap().take();
+        });
+        let (outgoing, mut frames) = mpsc::unbounded_channel::<Vec<u8>>();
+        let failed = pending.clone();
+        let writer = tokio::spawn(async move {
+            while let Some(frame) = frames.recv().await {
+                if writer.write_all(&frame).await.is_err() || writer.flush().await.is_err() {
+                    failed.lock().unwrap().take();
+                    return;
+                }
+            }
+        });
+        Self {
+            outgoing,
+            pending,
+            next_id: AtomicU32::new(0),
+            tasks: [reader, writer],
+        }
+    }
+
+    /// Requests a file by hash, `None` if the server doesn't have it.
+    pub async fn get_file(&self, hash: &str) -> Result<Option<File>> {
+        let response = self
+            .request(|id| Frame::GetFile {
+                id,
+                hash: hash.to_string(),
+            })
+            .await?;
+        response.into_file(hash)
+    }
+
+    async fn request(&self, frame: impl FnOnce(u32) -> Frame) -> Result<Frame> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let frame = frame(id).encode()?;
+        let (sender, receiver) = oneshot::channel();
+        match self.pending.lock().unwrap().as_mut() {
+            Some(pending) => pending.insert(id, sender),
+            None => return Err(closed().into()),
+        };
+        if self.outgoing.send(frame).is_err() {
+            return Err(closed().into());
+        }
+        receiver.await.map_err(|_| closed().into())
+    }
+}
+
+impl Drop for StreamAcquirer {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+fn closed() -> AcquireError {
+    AcquireError::Unavailable("Connection was closed".to_string())
+}
+
+#[async_trait]
+impl Acquirer for StreamAcquirer {
+    async fn get_block(&self, block: &Block) -> Result<Vec<u8>> {
+        let response = self
+            .request(|id| Frame::GetBlock {
+                id,
+                block: block.clone(),
+            })
+            .await?;
+        response.into_block_data(block)
+    }
+    /// Sends all requests right away and yields the blocks as they arrive.
+    fn get_blocks<'a>(&'a self, blocks: &'a [Block]) -> BoxStream<'a, (usize, Result<Vec<u8>>)> {
+        futures::stream::iter(blocks.iter().enumerate())
+            .map(move |(index, block)| async move { (index, self.get_block(block).await) })
+            .buffer_unordered(blocks.len().max(1))
+            .boxed()
+    }
+}
@@ -0,0 +1,262 @@
+//! The wire format. Every frame starts with its length as a big endian `u32`, not counting the
+//! length itself, followed by a type byte and the `u32` id of the request it belongs to. Strings
+//! carry a `u16` length and byte strings run to the end of the frame.
+//!
+//! | Type | Frame      | Payload after the id                                       |
+//! |------|------------|------------------------------------------------------------|
+//! | 1    | `GetBlock` | block length `u64`, hash                                   |
+//! | 2    | `Block`    | block data                                                 |
+//! | 3    | `GetFile`  | hash                                                       |
+//! | 4    | `File`     | hash, signature (empty if none), block count `u32`, blocks |
+//! | 5    | `Error`    | kind `u8`, message                                         |
+//!
+//! Blocks of a file are encoded as their length `u64` followed by their hash. Requests may be
+//! pipelined, responses come back in any order and are matched to requests by id.
+
+use anyhow::{anyhow, Context, Result};
+use incremental_file::{acquirer::AcquireError, block::Block, file::File};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Frames larger than this are refused, so a peer can't make the other side allocate at will.
+pub const MAX_FRAME_LENGTH: u32 = 64 * 1024 * 1024;
+
+const GET_BLOCK: u8 = 1;
+const BLOCK: u8 = 2;
+const GET_FILE: u8 = 3;
+const FILE: u8 = 4;
+const ERROR: u8 = 5;
+
+#[derive(Debug, Clone)]
+pub enum Frame {
+    GetBlock {
+        id: u32,
+        block: Block,
+    },
+    Block {
+        id: u32,
+        data: Vec<u8>,
+    },
+    GetFile {
+        id: u32,
+        hash: String,
+    },
+    File {
+        id: u32,
+        file: File,
+    },
+    /// Answers a request that failed. `NotFound` errors carry the requested hash.
+    Error {
+        id: u32,
+        error: AcquireError,
+    },
+}
+
+impl Frame {
+    pub fn id(&self) -> u32 {
+        match self {
+            Frame::GetBlock { id, .. }
+            | Frame::Block { id, .. }
+            | Frame::GetFile { id, .. }
+            | Frame::File { id, .. }
+            | Frame::Error { id, .. } => *id,
+        }
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        // Room for the length, filled in at the end
+        let mut buffer = vec![0; 4];
+        match self {
+            Frame::GetBlock { id, block } => {
+                buffer.push(GET_BLOCK);
+                buffer.extend(id.to_be_bytes());
+                buffer.extend(block.length.to_be_bytes());
+                put_string(&mut buffer, &block.hash)?;
+            }
+            Frame::Block { id, data } => {
+                buffer.push(BLOCK);
+                buffer.extend(id.to_be_bytes());
+                buffer.extend(data);
+            }
+            Frame::GetFile { id, hash } => {
+                buffer.push(GET_FILE);
+                buffer.extend(id.to_be_bytes());
+                put_string(&mut buffer, hash)?;
+            }
+            Frame::File { id, file } => {
+                buffer.push(FILE);
+                buffer.extend(id.to_be_bytes());
+                put_string(&mut buffer, &file.hash)?;
+                put_string(&mut buffer, file.signature.as_deref().unwrap_or_default())?;
+                buffer.extend((file.blocks.len() as u32).to_be_bytes());
+                for block in &file.blocks {
+                    buffer.extend(block.length.to_be_bytes());
+                    put_string(&mut buffer, &block.hash)?;
+                }
+            }
+            Frame::Error { id, error } => {
+                buffer.push(ERROR);
+                buffer.extend(id.to_be_bytes());
+                let (kind, message) = match error {
+                    AcquireError::NotFound(message) => (0, message),
+                    AcquireError::Unavailable(message) => (1, message),
+                    AcquireError::Rejected(message) => (2, message),
+                    AcquireError::InvalidData(message) => (3, message),
+                };
+                buffer.push(kind);
+                buffer.extend(message.as_bytes());
+            }
+        }
+        let length = u32::try_from(buffer.len() - 4)
+            .ok()
+            .filter(|length| *length <= MAX_FRAME_LENGTH)
+            .ok_or_else(|| anyhow!("Frame is larger than {} bytes", MAX_FRAME_LENGTH))?;
+        buffer[..4].copy_from_slice(&length.to_be_bytes());
+        Ok(buffer)
+    }
+
+    /// Reads the response to a `GetBlock` request for `block`, whose data must have the block's
+    /// length.
+    pub fn into_block_data(self, block: &Block) -> Result<Vec<u8>> {
+        match self {
+            Frame::Block { data, .. } if data.len() as u64 == block.length => Ok(data),
+            Frame::Block { .. } => Err(AcquireError::InvalidData(block.hash.clone()).into()),
+            Frame::Error {
+                error: AcquireError::NotFound(_),
+                ..
+            } => Err(AcquireError::NotFound(block.hash.clone()).into()),
+            Frame::Error { error, .. } => Err(error.into()),
+            _ => Err(anyhow!("Unexpected response to a block request")),
+        }
+    }
+    /// Reads the response to a `GetFile` request for `hash`, `None` if the server doesn't have it.
+    pub fn into_file(self, hash: &str) -> Result<Option<File>> {
+        match self {
+            Frame::File { file, .. } if file.hash == hash => Ok(Some(file)),
+            Frame::Error {
+                error: AcquireError::NotFound(_),
+                ..
+            } => Ok(None),
+            Frame::Error { error, .. } => Err(error.into()),
+            _ => Err(anyhow!("Unexpected response to a file request")),
+        }
+    }
+
+    /// Decodes a frame without its length prefix.
+    pub fn decode(bytes: &[u8]) -> Result<Frame> {
+        let mut reader = Reader { bytes };
+        let frame_type = reader.u8()?;
+        let id = reader.u32()?;
+        let frame = match frame_type {
+            GET_BLOCK => {
+                let length = reader.u64()?;
+                let hash = reader.string()?;
+                Frame::GetBlock {
+                    id,
+                    block: Block::new(length, hash),
+                }
+            }
+            BLOCK => Frame::Block {
+                id,
+                data: reader.rest().to_vec(),
+            },
+            GET_FILE => Frame::GetFile {
+                id,
+                hash: reader.string()?,
+            },
+            FILE => {
+                let hash = reader.string()?;
+                let signature = Some(reader.string()?).filter(|signature| !signature.is_empty());
+                let count = reader.u32()?;
+                let mut blocks = Vec::new();
+                for _ in 0..count {
+                    let length = reader.u64()?;
+                    blocks.push(Block::new(length, reader.string()?));
+                }
+                let mut file = File::new(blocks, hash);
+                file.signature = signature;
+                Frame::File { id, file }
+            }
+            ERROR => {
+                let kind = reader.u8()?;
+                let message = String::from_utf8_lossy(reader.rest()).into_owned();
+                let error = match kind {
+                    0 => AcquireError::NotFound(message),
+                    1 => AcquireError::Unavailable(message),
+                    2 => AcquireError::Rejected(message),
+                    3 => AcquireError::InvalidData(message),
+                    kind => return Err(anyhow!("Unknown error kind {}", kind)),
+                };
+                Frame::Error { id, error }
+            }
+            frame_type => return Err(anyhow!("Unknown frame type {}", frame_type)),
+        };
+        if !reader.bytes.is_empty() {
+            return Err(anyhow!("Frame has {} trailing bytes", reader.bytes.len()));
+        }
+        Ok(frame)
+    }
+}
+
+/// Reads the next frame, or `None` if the stream ended between frames.
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<Frame>> {
+    let mut length = [0; 4];
+    match reader.read_exact(&mut length).await {
+        Ok(_) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    }
+    let length = u32::from_be_bytes(length);
+    if length > MAX_FRAME_LENGTH {
+        return Err(anyhow!("Frame of {} bytes is too large", length));
+    }
+    let mut bytes = vec![0; length as usize];
+    reader
+        .read_exact(&mut bytes)
+        .await
+        .context("Stream ended inside a frame")?;
+    Frame::decode(&bytes).map(Some)
+}
+
+pub async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, frame: &Frame) -> Result<()> {
+    writer.write_all(&frame.encode()?).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+fn put_string(buffer: &mut Vec<u8>, string: &str) -> Result<()> {
+    let length = u16::try_from(string.len()).context("String is too long for a frame")?;
+    buffer.extend(length.to_be_bytes());
+    buffer.extend(string.as_bytes());
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < count {
+            return Err(anyhow!("Frame is truncated"));
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into()?))
+    }
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into()?))
+    }
+    fn string(&mut self) -> Result<String> {
+        let length = u16::from_be_bytes(self.take(2)?.try_into()?);
+        Ok(String::from_utf8(self.take(length as usize)?.to_vec())?)
+    }
+    fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.bytes)
+    }
+}
@@ -0,0 +1,183 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+use incremental_file::{acquirer::AcquireError, block::is_valid_hash, storage::Storage};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
+    net::TcpListener,
+    sync::{mpsc, Semaphore},
+};
+
+use crate::frame::{read_frame, Frame};
+
+/// Pause after a failed accept, so a listener that keeps failing, for example because the process
+/// ran out of file descriptors, doesn't spin.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Serves the blocks and files of a storage to `StreamAcquirer`s. Requests on a connection are
+/// handled concurrently and answered as soon as they are ready.
+pub struct StreamServer<S: Storage> {
+    storage: S,
+    max_requests: usize,
+}
+
+impl<S: Storage + 'static> StreamServer<S> {
+    pub fn new(storage: S) -> Self {
+        Self {
+            storage,
+            max_requests: 64,
+        }
+    }
+    /// Sets how many requests of one connection are handled at once, 64 by default. Further
+    /// requests aren't read until one of them is answered, so a client sending faster than the
+    /// server answers is slowed down by the connection instead of piling up work.
+    pub fn with_max_requests(mut self, max_requests: usize) -> Self {
+        self.max_requests = max_requests.max(1);
+        self
+    }
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+    pub fn max_requests(&self) -> usize {
+        self.max_requests
+    }
+
+    /// Accepts connections for as long as the server runs. A connection that fails while being
+    /// accepted is logged and skipped.
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> Result<()> {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(err) => {
+                    log::warn!("Failed to accept a connection: {}", err);
+                    tokio::time::sleep(ACCEPT_BACKOFF).await;
+                    continue;
+                }
+            };
+            if let Err(err) = stream.set_nodelay(true) {
+                log::warn!("Failed to configure a connection: {}", err);
+                continue;
+            }
+            tokio::spawn(self.clone().serve_stream(stream));
+        }
+    }
+    /// Accepts connections on a Unix domain socket like `serve`, so local processes can share one
+    /// storage through this server instead of opening it themselves.
+    #[cfg(unix)]
+    pub async fn serve_unix(self: Arc<Self>, listener: tokio::net::UnixListener) -> Result<()> {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(self.clone().serve_stream(stream));
+                }
+                Err(err) => {
+                    log::warn!("Failed to accept a connection: {}", err);
+                    tokio::time::sleep(ACCEPT_BACKOFF).await;
+                }
+            }
+        }
+    }
+    /// Speaks the protocol over any established connection until the client disconnects. A
+    /// malformed frame ends the connection, since the stream can't be trusted afterwards.
+    pub async fn serve_stream<T: AsyncRead + AsyncWrite + Send + 'static>(
+        self: Arc<Self>,
+        stream: T,
+    ) -> Result<()> {
+        let (mut reader, mut writer) = tokio::io::split(stream);
+        let (responses, mut outgoing) = mpsc::channel::<Vec<u8>>(self.max_requests);
+        let writer = tokio::spawn(async move {
+            while let Some(frame) = outgoing.recv().await {
+                writer.write_all(&frame).await?;
+                writer.flush().await?;
+            }
+            Ok::<_, std::io::Error>(())
+        });
+        // A request holds its permit until its response is queued for the writer
+        let requests = Arc::new(Semaphore::new(self.max_requests));
+        let result = loop {
+            let permit = requests
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("The semaphore is never closed");
+            let frame = match read_frame(&mut reader).await {
+                Ok(Some(frame)) => frame,
+                Ok(None) => break Ok(()),
+                Err(err) => break Err(err),
+            };
+            let server = self.clone();
+            let responses = responses.clone();
+            tokio::spawn(async move {
+                if let Ok(encoded) = respond(&server.storage, frame).await {
+                    let _ = responses.send(encoded).await;
+                }
+                drop(permit);
+            });
+        };
+        // The writer finishes once every pending response has been sent
+        drop(responses);
+        writer.await??;
+        result
+    }
+}
+
+/// Answers one request from the storage with an encoded response frame, for any transport carrying
+/// frames. Responses too large to encode are answered with a `Rejected` error instead. Malformed
+/// hashes are not found without asking the storage, and storage failures are logged while the
+/// client only learns that the storage is unavailable.
+pub async fn respond<S: Storage>(storage: &S, request: Frame) -> Result<Vec<u8>> {
+    let response = match request {
+        Frame::GetBlock { id, block } if !is_valid_hash(&block.hash) => Frame::Error {
+            id,
+            error: AcquireError::NotFound(block.hash),
+        },
+        Frame::GetBlock { id, block } => match storage.get_block_data(&block).await {
+            Ok(Some(data)) => Frame::Block { id, data },
+            Ok(None) => Frame::Error {
+                id,
+                error: AcquireError::NotFound(block.hash),
+            },
+            Err(err) => {
+                log::warn!("Failed to read block {}: {:#}", block.hash, err);
+                Frame::Error {
+                    id,
+                    error: storage_failed(),
+                }
+            }
+        },
+        Frame::GetFile { id, hash } if !is_valid_hash(&hash) => Frame::Error {
+            id,
+            error: AcquireError::NotFound(hash),
+        },
+        Frame::GetFile { id, hash } => match storage.get_file(&hash).await {
+            Ok(Some(file)) => Frame::File { id, file },
+            Ok(None) => Frame::Error {
+                id,
+                error: AcquireError::NotFound(hash),
+            },
+            Err(err) => {
+                log::warn!("Failed to read file {}: {:#}", hash, err);
+                Frame::Error {
+                    id,
+                    error: storage_failed(),
+                }
+            }
+        },
+        response => Frame::Error {
+            id: response.id(),
+            error: AcquireError::Rejected("Only requests can be sent to a server".to_string()),
+        },
+    };
+    response.encode().or_else(|err| {
+        Frame::Error {
+            id: response.id(),
+            error: AcquireError::Rejected(format!("{:#}", err)),
+        }
+        .encode()
+    })
+}
+
+/// What clients are told about a failing storage, the details stay in the server's log.
+fn storage_failed() -> AcquireError {
+    AcquireError::Unavailable("The storage failed".to_string())
+}
@@ -0,0 +1,180 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+use futures::StreamExt;
+use incremental_file::{
+    acquirer::{AcquireError, Acquirer},
+    block::Block,
+    downloader::Downloader,
+    file::File,
+    storage::{MemoryStorage, Storage},
+};
+use incremental_file_tcp::{
+    client::StreamAcquirer,
+    frame::{read_frame, Frame, MAX_FRAME_LENGTH},
+    server::StreamServer,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+async fn start(storage: MemoryStorage) -> Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?.to_string();
+    tokio::spawn(Arc::new(StreamServer::new(storage)).serve(listener));
+    Ok(address)
+}
+
+async fn origin(data: &[u8]) -> Result<(MemoryStorage, File)> {
+    let mut storage = MemoryStorage::new();
+    let file = File::from_data(data, 10, &mut storage).await?;
+    storage.upsert_file(&file).await?;
+    Ok((storage, file))
+}
+
+#[tokio::test]
+async fn downloads_file_over_loopback() -> Result<()> {
+    let data = (0..255).collect::<Vec<u8>>();
+    let (origin, file) = origin(&data).await?;
+    let address = start(origin).await?;
+    let acquirer = StreamAcquirer::connect(&address).await?;
+
+    let fetched = acquirer.get_file(&file.hash).await?.expect("File exists");
+    assert_eq!(fetched.hash, file.hash);
+    let mut storage = MemoryStorage::new();
+    let report = Downloader::new(acquirer)
+        .with_concurrency(4)
+        .with_batch_size(8)
+        .download(&fetched, &mut storage)
+        .await?;
+    assert!(report.is_complete());
+    assert_eq!(file.data(&storage).await?, data);
+    Ok(())
+}
+#[tokio::test]
+async fn requests_are_pipelined() -> Result<()> {
+    let data = (0..255).collect::<Vec<u8>>();
+    let (origin, file) = origin(&data).await?;
+    let address = start(origin).await?;
+    let acquirer = StreamAcquirer::connect(&address).await?;
+
+    let mut results = acquirer.get_blocks(&file.blocks).collect::<Vec<_>>().await;
+    results.sort_by_key(|(index, _)| *index);
+    assert_eq!(results.len(), file.blocks.len());
+    for ((_, result), chunk) in results.into_iter().zip(data.chunks(10)) {
+        assert_eq!(result?, chunk);
+    }
+    Ok(())
+}
+#[tokio::test]
+async fn missing_blocks_and_files_are_not_found() -> Result<()> {
+    let address = start(MemoryStorage::new()).await?;
+    let acquirer = StreamAcquirer::connect(&address).await?;
+    let block = Block::from_data([1, 2, 3]);
+    let err = acquirer.get_block(&block).await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<AcquireError>(),
+        Some(AcquireError::NotFound(hash)) if *hash == block.hash
+    ));
+    assert!(acquirer.get_file("0123").await?.is_none());
+    Ok(())
+}
+#[tokio::test]
+async fn frames_round_trip() -> Result<()> {
+    let data = (0..100).collect::<Vec<u8>>();
+    let (_, mut file) = origin(&data).await?;
+    file.signature = Some("abcd".to_string());
+    let frames = [
+        Frame::GetBlock {
+            id: 1,
+            block: file.blocks[0].clone(),
+        },
+        Frame::Block {
+            id: 2,
+            data: data.clone(),
+        },
+        Frame::GetFile {
+            id: 3,
+            hash: file.hash.clone(),
+        },
+        Frame::File {
+            id: 4,
+            file: file.clone(),
+        },
+        Frame::Error {
+            id: 5,
+            error: AcquireError::Rejected("No".to_string()),
+        },
+    ];
+    for frame in frames {
+        let encoded = frame.encode()?;
+        let decoded = Frame::decode(&encoded[4..])?;
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", frame));
+    }
+    assert!(Frame::decode(&[9, 0, 0, 0, 1]).is_err());
+    assert!(Frame::decode(&[1, 0, 0]).is_err());
+    Ok(())
+}
+#[tokio::test]
+async fn server_hangs_up_on_oversized_frames() -> Result<()> {
+    let address = start(MemoryStorage::new()).await?;
+    let mut stream = TcpStream::connect(&address).await?;
+    stream
+        .write_all(&(MAX_FRAME_LENGTH + 1).to_be_bytes())
+        .await?;
+    let mut buffer = Vec::new();
+    assert_eq!(stream.read_to_end(&mut buffer).await?, 0);
+    Ok(())
+}
+#[tokio::test]
+async fn lost_connection_is_unavailable() -> Result<()> {
+    // Accepts one connection and closes it right away
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?;
+    tokio::spawn(async move {
+        let _ = listener.accept().await;
+    });
+    let acquirer = StreamAcquirer::connect(address).await?;
+    let err = acquirer
+        .get_block(&Block::from_data([1, 2, 3]))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<AcquireError>(),
+        Some(AcquireError::Unavailable(_))
+    ));
+    Ok(())
+}
+#[tokio::test]
+async fn clients_that_dont_read_responses_are_slowed_down() -> Result<()> {
+    let data = (0..100).collect::<Vec<u8>>();
+    let (origin, file) = origin(&data).await?;
+    let server = Arc::new(StreamServer::new(origin).with_max_requests(2));
+    let (client, stream) = tokio::io::duplex(1024);
+    tokio::spawn(server.serve_stream(stream));
+    let (mut reader, mut writer) = tokio::io::split(client);
+
+    let requests = (0..200)
+        .map(|id| {
+            Frame::GetBlock {
+                id,
+                block: file.blocks[id as usize % 10].clone(),
+            }
+            .encode()
+        })
+        .collect::<Result<Vec<_>>>()?
+        .concat();
+    let mut sending = tokio::spawn(async move { writer.write_all(&requests).await });
+    // Responses pile up unread, so the server stops reading requests
+    let stalled = tokio::time::timeout(Duration::from_millis(100), &mut sending).await;
+    assert!(stalled.is_err());
+
+    let mut received = 0;
+    while received < 200 {
+        assert!(read_frame(&mut reader).await?.is_some());
+        received += 1;
+    }
+    sending.await??;
+    Ok(())
+}
@@ -0,0 +1,128 @@
+#![cfg(unix)]
+
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::Result;
+use incremental_file::{
+    acquirer::{AcquireError, Acquirer},
+    block::Block,
+    converter::Converter,
+    downloader::Downloader,
+    file::File,
+    storage::{MemoryStorage, Storage},
+};
+use incremental_file_converter_json::JsonConverter;
+use incremental_file_local::storage::FileSystemStorage;
+use incremental_file_tcp::{client::StreamAcquirer, server::StreamServer};
+use tokio::net::UnixListener;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "incremental-file-unix-{}-{}",
+        name,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[tokio::test]
+async fn processes_share_one_storage_through_a_daemon() -> Result<()> {
+    let dir = temp_dir("daemon");
+    let data = (0..255).collect::<Vec<u8>>();
+    let mut cache = FileSystemStorage::new(dir.join("cache"), JsonConverter {});
+    let file = File::from_data(&data, 10, &mut cache).await?;
+    cache.upsert_file(&file).await?;
+
+    let socket = dir.join("daemon.sock");
+    let listener = UnixListener::bind(&socket)?;
+    tokio::spawn(Arc::new(StreamServer::new(cache)).serve_unix(listener));
+
+    // Every tool connects on its own and downloads into its own storage
+    let mut downloads = Vec::new();
+    for _ in 0..3 {
+        let socket = socket.clone();
+        let hash = file.hash.clone();
+        downloads.push(tokio::spawn(async move {
+            let acquirer = StreamAcquirer::connect_unix(&socket).await?;
+            let file = acquirer.get_file(&hash).await?.expect("File exists");
+            let mut storage = MemoryStorage::new();
+            let report = Downloader::new(acquirer)
+                .with_concurrency(4)
+                .download(&file, &mut storage)
+                .await?;
+            assert!(report.is_complete());
+            file.data(&storage).await
+        }));
+    }
+    for download in downloads {
+        assert_eq!(download.await??, data);
+    }
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+#[tokio::test]
+async fn missing_blocks_are_not_found_over_unix_socket() -> Result<()> {
+    let dir = temp_dir("missing");
+    let socket = dir.join("daemon.sock");
+    let listener = UnixListener::bind(&socket)?;
+    tokio::spawn(Arc::new(StreamServer::new(MemoryStorage::new())).serve_unix(listener));
+
+    let acquirer = StreamAcquirer::connect_unix(&socket).await?;
+    let err = acquirer
+        .get_block(&Block::from_data([1, 2, 3]))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<AcquireError>(),
+        Some(AcquireError::NotFound(_))
+    ));
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+#[tokio::test]
+async fn paths_outside_the_storage_are_not_found() -> Result<()> {
+    let dir = temp_dir("traversal");
+    // A file manifest next to the storage, which `../..` would reach from its file directory
+    let file = File::from_data([1, 2, 3], 10, &mut MemoryStorage::new()).await?;
+    std::fs::write(dir.join("outside"), JsonConverter {}.serialize_file(&file)?)?;
+    let storage = FileSystemStorage::new(dir.join("cache"), JsonConverter {});
+    let socket = dir.join("daemon.sock");
+    tokio::spawn(Arc::new(StreamServer::new(storage)).serve_unix(UnixListener::bind(&socket)?));
+
+    let acquirer = StreamAcquirer::connect_unix(&socket).await?;
+    assert!(acquirer.get_file("../../outside").await?.is_none());
+    let block = Block::new(3, "../../outside".to_string());
+    let err = acquirer.get_block(&block).await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<AcquireError>(),
+        Some(AcquireError::NotFound(_))
+    ));
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+#[tokio::test]
+async fn storage_errors_are_not_sent_to_clients() -> Result<()> {
+    let dir = temp_dir("failing");
+    // The storage can't create its directories below a regular file
+    std::fs::write(dir.join("cache"), [])?;
+    let storage = FileSystemStorage::new(dir.join("cache"), JsonConverter {});
+    let socket = dir.join("daemon.sock");
+    tokio::spawn(Arc::new(StreamServer::new(storage)).serve_unix(UnixListener::bind(&socket)?));
+
+    let acquirer = StreamAcquirer::connect_unix(&socket).await?;
+    let block = Block::from_data([1, 2, 3]);
+    let errors = [
+        acquirer.get_block(&block).await.unwrap_err(),
+        acquirer.get_file(&block.hash).await.unwrap_err(),
+    ];
+    for err in errors {
+        assert!(matches!(
+            err.downcast_ref::<AcquireError>(),
+            Some(AcquireError::Unavailable(reason)) if reason == "The storage failed"
+        ));
+    }
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -0,0 +1,199 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use super::{is_retryable, AcquireError, Acquirer, BoxedAcquirer, ServedBy};
+use crate::block::Block;
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::{
+    stream::{self, BoxStream},
+    StreamExt,
+};
+
+/// Blocks whose serving mirror is remembered by `MirrorAcquirer::served_by`.
+const SERVED_BY_CAPACITY: usize = 4096;
+
+#[derive(Default)]
+struct Health {
+    consecutive_failures: u32,
+    unhealthy_until: Option<Instant>,
+}
+
+/// Requests every block from a list of mirrors in priority order, moving on to the next one when
+/// a mirror fails or returns invalid data. Mirrors that keep failing are skipped for a cooldown period.
+pub struct MirrorAcquirer {
+    mirrors: Vec<BoxedAcquirer>,
+    health: Vec<Mutex<Health>>,
+    failure_threshold: u32,
+    cooldown: Duration,
+    /// Blocks served by each mirror.
+    served: Vec<AtomicU64>,
+    served_by: ServedBy,
+}
+
+impl MirrorAcquirer {
+    pub fn new(mirrors: Vec<BoxedAcquirer>) -> Self {
+        let health = mirrors.iter().map(|_| Mutex::default()).collect();
+        let served = mirrors.iter().map(|_| AtomicU64::new(0)).collect();
+        Self {
+            mirrors,
+            health,
+            failure_threshold: 3,
+            cooldown: Duration::from_secs(30),
+            served,
+            served_by: ServedBy::new(SERVED_BY_CAPACITY),
+        }
+    }
+    /// Sets how many failures in a row make a mirror unhealthy.
+    pub fn with_failure_threshold(mut self, failure_threshold: u32) -> Self {
+        self.failure_threshold = failure_threshold.max(1);
+        self
+    }
+    /// Sets how long an unhealthy mirror is skipped before it is tried again.
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+    /// A mirror whose cooldown is over is healthy again and starts over counting its failures.
+    pub fn is_healthy(&self, mirror: usize) -> bool {
+        let mut health = self.health[mirror].lock().unwrap();
+        match health.unhealthy_until {
+            Some(until) if Instant::now() >= until => {
+                *health = Health::default();
+                true
+            }
+            Some(_) => false,
+            None => true,
+        }
+    }
+    /// Number of blocks the mirror has served.
+    pub fn served(&self, mirror: usize) -> u64 {
+        self.served[mirror].load(Ordering::Relaxed)
+    }
+    /// Index of the mirror that served the last successful request for the block, remembered for
+    /// the last 4096 blocks served.
+    pub fn served_by(&self, block: &Block) -> Option<usize> {
+        self.served_by.get(block)
+    }
+
+    async fn try_mirror(&self, index: usize, block: &Block) -> Result<Vec<u8>> {
+        let data = self.mirrors[index].get_block(block).await?;
+        if block.validate(&data).is_err() {
+            return Err(AcquireError::InvalidData(block.hash.clone()).into());
+        }
+        Ok(data)
+    }
+    /// Tries the mirrors in order, healthy ones first, skipping the one that already failed with
+    /// the given error.
+    async fn fail_over(
+        &self,
+        block: &Block,
+        failed: Option<(usize, anyhow::Error)>,
+    ) -> Result<Vec<u8>> {
+        let (tried, mut error) = match failed {
+            Some((tried, err)) => (Some(tried), Some(err)),
+            None => (None, None),
+        };
+        let (healthy, cooling_down): (Vec<usize>, Vec<usize>) = (0..self.mirrors.len())
+            .filter(|index| Some(*index) != tried)
+            .partition(|index| self.is_healthy(*index));
+        // Unhealthy mirrors are a last resort, better than failing the block outright
+        for index in healthy.into_iter().chain(cooling_down) {
+            match self.try_mirror(index, block).await {
+                Ok(data) => {
+                    self.record_success(index, block);
+                    return Ok(data);
+                }
+                Err(err) => {
+                    self.record_error(index, &err);
+                    // Prefer reporting a retryable error, asking again may still succeed
+                    if error
+                        .as_ref()
+                        .map(|error| !is_retryable(error))
+                        .unwrap_or(true)
+                    {
+                        error = Some(err);
+                    }
+                }
+            }
+        }
+        match error {
+            Some(err) => Err(err.context(format!("All mirrors failed for block {}", block.hash))),
+            None => Err(AcquireError::NotFound(block.hash.clone()).into()),
+        }
+    }
+    fn record_success(&self, index: usize, block: &Block) {
+        *self.health[index].lock().unwrap() = Health::default();
+        self.served[index].fetch_add(1, Ordering::Relaxed);
+        self.served_by.insert(block, index);
+    }
+    /// Counts a failure against the mirror. A mirror that lacks a block is still a healthy mirror.
+    fn record_error(&self, index: usize, err: &anyhow::Error) {
+        let not_found = matches!(
+            err.downcast_ref::<AcquireError>(),
+            Some(AcquireError::NotFound(_))
+        );
+        if !not_found {
+            self.record_failure(index);
+        }
+    }
+    fn record_failure(&self, index: usize) {
+        let mut health = self.health[index].lock().unwrap();
+        health.consecutive_failures += 1;
+        if health.consecutive_failures >= self.failure_threshold {
+            health.unhealthy_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+}
+
+#[async_trait]
+impl Acquirer for MirrorAcquirer {
+    async fn get_block(&self, block: &Block) -> Result<Vec<u8>> {
+        self.fail_over(block, None).await
+    }
+    /// Sends the batch to the first healthy mirror, the blocks it fails to deliver fail over to
+    /// the other mirrors one by one.
+    fn get_blocks<'a>(&'a self, blocks: &'a [Block]) -> BoxStream<'a, (usize, Result<Vec<u8>>)> {
+        let first =
+            match (0..self.mirrors.len()).find(|index| self.is_healthy(*index)) {
+                Some(first) => first,
+                None => return stream::iter(blocks.iter().enumerate())
+                    .then(move |(index, block)| async move { (index, self.get_block(block).await) })
+                    .boxed(),
+            };
+        self.mirrors[first]
+            .get_blocks(blocks)
+            .then(move |(index, result)| async move {
+                let block = match blocks.get(index) {
+                    Some(block) => block,
+                    None => return (index, result),
+                };
+                let result = result.and_then(|data| match block.validate(&data) {
+                    Ok(()) => Ok(data),
+                    Err(_) => Err(AcquireError::InvalidData(block.hash.clone()).into()),
+                });
+                let result = match result {
+                    Ok(data) => {
+                        self.record_success(first, block);
+                        Ok(data)
+                    }
+                    Err(err) => {
+                        self.record_error(first, &err);
+                        self.fail_over(block, Some((first, err))).await
+                    }
+                };
+                (index, result)
+            })
+            .boxed()
+    }
+    fn set_remaining(&self, remaining: usize) {
+        for mirror in &self.mirrors {
+            mirror.set_remaining(remaining);
+        }
+    }
+}
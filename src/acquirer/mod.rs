@@ -0,0 +1,122 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    sync::Mutex,
+};
+
+use crate::block::Block;
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::{stream::BoxStream, StreamExt};
+
+pub mod mirror;
+pub mod race;
+pub mod retry;
+pub mod storage;
+
+pub type BoxedAcquirer = Box<dyn Acquirer>;
+/// Reads or downloads file blocks from the external source.
+#[async_trait]
+pub trait Acquirer: Send + Sync {
+    async fn get_block(&self, block: &Block) -> Result<Vec<u8>>;
+    /// Requests several blocks at once, yielding every result together with the position of its
+    /// block in `blocks`, in any order. Transports that can serve many blocks per round trip should
+    /// override it, by default the blocks are requested one after another.
+    fn get_blocks<'a>(&'a self, blocks: &'a [Block]) -> BoxStream<'a, (usize, Result<Vec<u8>>)> {
+        futures::stream::iter(blocks.iter().enumerate())
+            .then(move |(index, block)| async move { (index, self.get_block(block).await) })
+            .boxed()
+    }
+    /// Called by downloaders with the number of blocks that are still outstanding, so an acquirer
+    /// can change its strategy near the end of a download. Wrappers should pass it on.
+    fn set_remaining(&self, _remaining: usize) {}
+}
+
+#[async_trait]
+impl<A: Acquirer + ?Sized> Acquirer for Box<A> {
+    async fn get_block(&self, block: &Block) -> Result<Vec<u8>> {
+        (**self).get_block(block).await
+    }
+    fn get_blocks<'a>(&'a self, blocks: &'a [Block]) -> BoxStream<'a, (usize, Result<Vec<u8>>)> {
+        (**self).get_blocks(blocks)
+    }
+    fn set_remaining(&self, remaining: usize) {
+        (**self).set_remaining(remaining)
+    }
+}
+
+/// Remembers which source served each of the most recently served blocks, so it stays small
+/// however many blocks go through an acquirer.
+pub(crate) struct ServedBy {
+    capacity: usize,
+    served: Mutex<(HashMap<String, usize>, VecDeque<String>)>,
+}
+
+impl ServedBy {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            served: Mutex::default(),
+        }
+    }
+    pub(crate) fn get(&self, block: &Block) -> Option<usize> {
+        self.served.lock().unwrap().0.get(&block.hash).copied()
+    }
+    pub(crate) fn insert(&self, block: &Block, source: usize) {
+        let mut served = self.served.lock().unwrap();
+        let (sources, order) = &mut *served;
+        if sources.insert(block.hash.clone(), source).is_none() {
+            order.push_back(block.hash.clone());
+        }
+        while order.len() > self.capacity {
+            if let Some(hash) = order.pop_front() {
+                sources.remove(&hash);
+            }
+        }
+    }
+}
+
+/// Failures an acquirer can report in a structured way, so callers can tell whether asking again makes sense.
+/// Acquirers return it wrapped in an `anyhow::Error`, use `is_retryable` to inspect any error.
+#[derive(Debug, Clone)]
+pub enum AcquireError {
+    /// The source doesn't have the block.
+    NotFound(String),
+    /// The source failed in a way that may go away, like a server error or a dropped connection.
+    Unavailable(String),
+    /// The source refused the request and will keep refusing it.
+    Rejected(String),
+    /// The data received doesn't match the block.
+    InvalidData(String),
+}
+
+impl AcquireError {
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            AcquireError::Unavailable(_) | AcquireError::InvalidData(_)
+        )
+    }
+}
+
+impl fmt::Display for AcquireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AcquireError::NotFound(hash) => write!(f, "Block with hash {} was not found", hash),
+            AcquireError::Unavailable(reason) => write!(f, "Source is unavailable: {}", reason),
+            AcquireError::Rejected(reason) => write!(f, "Request was rejected: {}", reason),
+            AcquireError::InvalidData(hash) => {
+                write!(f, "Data received for block with hash {} is invalid", hash)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AcquireError {}
+
+/// Errors that aren't an `AcquireError` are unknown transport failures and are treated as retryable.
+pub fn is_retryable(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<AcquireError>()
+        .map(|err| err.is_retryable())
+        .unwrap_or(true)
+}
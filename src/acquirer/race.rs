@@ -0,0 +1,279 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use super::{AcquireError, Acquirer, BoxedAcquirer, ServedBy};
+use crate::block::Block;
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::{
+    future,
+    stream::{self, BoxStream},
+    FutureExt, StreamExt,
+};
+use tokio::sync::watch;
+
+/// Weight of the newest sample in the moving averages.
+const SMOOTHING: f64 = 0.3;
+/// Blocks whose serving source is remembered by `RacingAcquirer::served_by`.
+const SERVED_BY_CAPACITY: usize = 4096;
+
+/// What a `RacingAcquirer` has measured about one of its sources.
+#[derive(Debug, Clone, Default)]
+pub struct SourceStats {
+    /// Moving average of the time a successful request took.
+    pub latency: Option<Duration>,
+    /// Moving average of bytes per second over successful requests.
+    pub throughput: Option<f64>,
+    pub in_flight: usize,
+    pub requests: u64,
+    pub failures: u64,
+}
+
+impl SourceStats {
+    /// Expected time until a new request of the given length completes, given the requests
+    /// already in flight. Sources without measurements are expected to be instant so they get tried.
+    fn estimate(&self, length: u64) -> f64 {
+        match self.throughput {
+            Some(throughput) => (self.in_flight + 1) as f64 * length as f64 / throughput,
+            None => 0.0,
+        }
+    }
+}
+
+/// Decrements the in-flight counter of a source however its request ends, including cancellation.
+struct InFlight<'a> {
+    stats: &'a Mutex<SourceStats>,
+}
+
+impl<'a> InFlight<'a> {
+    fn start(stats: &'a Mutex<SourceStats>) -> Self {
+        stats.lock().unwrap().in_flight += 1;
+        Self { stats }
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.stats.lock().unwrap().in_flight -= 1;
+    }
+}
+
+/// Measures a batch sent to one source, which is recorded as a single request once the batch is
+/// dropped: from its start to the last block received, with the bytes of every valid block.
+struct Batch<'a> {
+    acquirer: &'a RacingAcquirer,
+    source: usize,
+    _in_flight: InFlight<'a>,
+    start: Instant,
+    received: u64,
+    last: Option<Duration>,
+    failed: bool,
+}
+
+impl<'a> Batch<'a> {
+    fn start(acquirer: &'a RacingAcquirer, source: usize) -> Self {
+        Self {
+            acquirer,
+            source,
+            _in_flight: InFlight::start(&acquirer.stats[source]),
+            start: Instant::now(),
+            received: 0,
+            last: None,
+            failed: false,
+        }
+    }
+    fn received(&mut self, result: &Result<Vec<u8>>) {
+        match result {
+            Ok(data) => self.received += data.len() as u64,
+            Err(_) => self.failed = true,
+        }
+        self.last = Some(self.start.elapsed());
+    }
+}
+
+impl Drop for Batch<'_> {
+    fn drop(&mut self) {
+        // A batch dropped before anything arrived says nothing about the source
+        if let Some(elapsed) = self.last {
+            self.acquirer
+                .record(self.source, self.received, elapsed, !self.failed);
+        }
+    }
+}
+
+/// Spreads block requests over several sources, sending each one to the source expected to finish
+/// it first based on measured throughput and current load. Once only a few blocks of a download are
+/// outstanding (see `Acquirer::set_remaining`) it enters endgame: every request is duplicated to the
+/// other sources, the first valid copy wins and the other requests are cancelled.
+pub struct RacingAcquirer {
+    sources: Vec<BoxedAcquirer>,
+    stats: Vec<Mutex<SourceStats>>,
+    endgame_threshold: usize,
+    endgame: watch::Sender<bool>,
+    served_by: ServedBy,
+}
+
+impl RacingAcquirer {
+    pub fn new(sources: Vec<BoxedAcquirer>) -> Self {
+        let stats = sources.iter().map(|_| Mutex::default()).collect();
+        Self {
+            sources,
+            stats,
+            endgame_threshold: 4,
+            endgame: watch::channel(false).0,
+            served_by: ServedBy::new(SERVED_BY_CAPACITY),
+        }
+    }
+    /// Sets how many outstanding blocks start the endgame, 0 disables it.
+    pub fn with_endgame_threshold(mut self, endgame_threshold: usize) -> Self {
+        self.endgame_threshold = endgame_threshold;
+        self
+    }
+    pub fn stats(&self, source: usize) -> SourceStats {
+        self.stats[source].lock().unwrap().clone()
+    }
+    pub fn is_endgame(&self) -> bool {
+        *self.endgame.borrow()
+    }
+    /// Index of the source that delivered the last successful copy of the block, remembered for
+    /// the last 4096 blocks served.
+    pub fn served_by(&self, block: &Block) -> Option<usize> {
+        self.served_by.get(block)
+    }
+
+    /// Source indices ordered by how soon each is expected to deliver the given number of bytes.
+    fn ranked(&self, length: u64) -> Vec<usize> {
+        let mut estimates = self
+            .stats
+            .iter()
+            .map(|stats| stats.lock().unwrap().estimate(length))
+            .enumerate()
+            .collect::<Vec<_>>();
+        estimates.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+        estimates.into_iter().map(|(index, _)| index).collect()
+    }
+
+    async fn fetch(&self, index: usize, block: &Block) -> Result<Vec<u8>> {
+        let _in_flight = InFlight::start(&self.stats[index]);
+        let start = Instant::now();
+        let result = self.sources[index].get_block(block).await;
+        let result = self.check(index, block, result);
+        let bytes = result.as_ref().map(|data| data.len() as u64).unwrap_or(0);
+        self.record(index, bytes, start.elapsed(), result.is_ok());
+        result
+    }
+    /// Validates what a source returned for the block, remembering who served it.
+    fn check(&self, index: usize, block: &Block, result: Result<Vec<u8>>) -> Result<Vec<u8>> {
+        let data = result?;
+        if block.validate(&data).is_err() {
+            return Err(AcquireError::InvalidData(block.hash.clone()).into());
+        }
+        self.served_by.insert(block, index);
+        Ok(data)
+    }
+    /// Updates the measurements of a source with a request that took `elapsed` to deliver `bytes`.
+    fn record(&self, index: usize, bytes: u64, elapsed: Duration, success: bool) {
+        let mut stats = self.stats[index].lock().unwrap();
+        stats.requests += 1;
+        if success {
+            let throughput = bytes as f64 / elapsed.as_secs_f64().max(1e-6);
+            stats.throughput = Some(match stats.throughput {
+                Some(average) => average + SMOOTHING * (throughput - average),
+                None => throughput,
+            });
+            stats.latency = Some(match stats.latency {
+                Some(average) => average.mul_f64(1.0 - SMOOTHING) + elapsed.mul_f64(SMOOTHING),
+                None => elapsed,
+            });
+        } else {
+            // A failing source shouldn't keep winning the ranking on old measurements
+            stats.failures += 1;
+            stats.throughput = stats.throughput.map(|throughput| throughput / 2.0);
+        }
+    }
+}
+
+#[async_trait]
+impl Acquirer for RacingAcquirer {
+    async fn get_block(&self, block: &Block) -> Result<Vec<u8>> {
+        let ranked = self.ranked(block.length);
+        let mut endgame = self.endgame.subscribe();
+        let mut error = None;
+        for (position, &index) in ranked.iter().enumerate() {
+            let mut request = self.fetch(index, block).boxed();
+            let result = if *endgame.borrow_and_update() {
+                None
+            } else {
+                tokio::select! {
+                    result = &mut request => Some(result),
+                    _ = endgame.wait_for(|endgame| *endgame) => None,
+                }
+            };
+            let result = match result {
+                Some(result) => result,
+                None => {
+                    // Race the pending request against every source that wasn't tried yet
+                    let mut requests = vec![request];
+                    requests.extend(
+                        ranked[position + 1..]
+                            .iter()
+                            .map(|&other| self.fetch(other, block).boxed()),
+                    );
+                    return future::select_ok(requests).await.map(|(data, _)| data);
+                }
+            };
+            match result {
+                Ok(data) => return Ok(data),
+                Err(err) => error = Some(err),
+            }
+        }
+        match error {
+            Some(err) => Err(err.context(format!("All sources failed for block {}", block.hash))),
+            None => Err(AcquireError::NotFound(block.hash.clone()).into()),
+        }
+    }
+    /// Sends the batch to the source expected to finish it first, the blocks that fail there are
+    /// raced like single requests. The whole batch counts as one request of the source. In
+    /// endgame every block is raced on its own.
+    fn get_blocks<'a>(&'a self, blocks: &'a [Block]) -> BoxStream<'a, (usize, Result<Vec<u8>>)> {
+        let length = blocks.iter().map(|block| block.length).sum();
+        let source =
+            match self.ranked(length).first() {
+                Some(&source) if !self.is_endgame() => source,
+                _ => return stream::iter(blocks.iter().enumerate())
+                    .then(move |(index, block)| async move { (index, self.get_block(block).await) })
+                    .boxed(),
+            };
+        let mut batch = Batch::start(self, source);
+        self.sources[source]
+            .get_blocks(blocks)
+            .then(move |(index, result)| {
+                let block = blocks.get(index);
+                let result = match block {
+                    Some(block) => self.check(source, block, result),
+                    None => result,
+                };
+                batch.received(&result);
+                async move {
+                    match (block, result) {
+                        (Some(block), Err(_)) => (index, self.get_block(block).await),
+                        (_, result) => (index, result),
+                    }
+                }
+            })
+            .boxed()
+    }
+    fn set_remaining(&self, remaining: usize) {
+        let endgame = remaining > 0 && remaining <= self.endgame_threshold;
+        self.endgame.send_if_modified(|current| {
+            let modified = *current != endgame;
+            *current = endgame;
+            modified
+        });
+        for source in &self.sources {
+            source.set_remaining(remaining);
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use super::{is_retryable, AcquireError, Acquirer};
+use crate::block::Block;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures::{stream::BoxStream, StreamExt};
+use rand::Rng;
+use tokio::time::Instant;
+
+/// How often and how patiently a `RetryingAcquirer` asks for a block.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total number of attempts per block, including the first one.
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled (by `multiplier`) for every following one.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: f64,
+    /// Fraction of each backoff that is randomized, between 0.0 (none) and 1.0 (full jitter).
+    pub jitter: f64,
+    /// Time limit for a single attempt.
+    pub attempt_timeout: Option<Duration>,
+    /// Time limit for all attempts of a block together, including the backoffs.
+    pub deadline: Option<Duration>,
+    /// Number of times a block may arrive with the wrong hash before giving up on it.
+    pub max_hash_mismatches: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 2.0,
+            jitter: 0.5,
+            attempt_timeout: None,
+            deadline: None,
+            max_hash_mismatches: 2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Checks that the backoff can be computed for every attempt.
+    pub fn validate(&self) -> Result<()> {
+        if self.max_attempts == 0 {
+            return Err(anyhow!("A retry policy needs at least one attempt"));
+        }
+        if !self.multiplier.is_finite() || self.multiplier <= 0.0 {
+            return Err(anyhow!(
+                "Backoff multiplier must be a positive number, not {}",
+                self.multiplier
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.jitter) {
+            return Err(anyhow!(
+                "Jitter must be between 0.0 and 1.0, not {}",
+                self.jitter
+            ));
+        }
+        Ok(())
+    }
+    /// The wait after the given failed attempt, counting from 1.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let backoff = self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent);
+        let backoff = backoff.min(self.max_backoff.as_secs_f64());
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let factor = 1.0 - jitter * rand::thread_rng().gen::<f64>();
+        // Only reachable with a policy that doesn't validate
+        Duration::try_from_secs_f64(backoff * factor).unwrap_or(self.max_backoff)
+    }
+}
+
+/// Retries failed requests of the inner acquirer according to a `RetryPolicy`. Received data is
+/// validated, so a corrupted transfer is retried like any other temporary failure.
+pub struct RetryingAcquirer<A: Acquirer> {
+    inner: A,
+    policy: RetryPolicy,
+}
+
+impl<A: Acquirer> RetryingAcquirer<A> {
+    /// Fails if the policy doesn't validate.
+    pub fn new(inner: A, policy: RetryPolicy) -> Result<Self> {
+        policy.validate()?;
+        Ok(Self { inner, policy })
+    }
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+    pub fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    /// Requests the block until it arrives or the policy gives up. An error passed in counts as
+    /// the first failed attempt.
+    async fn retry(
+        &self,
+        block: &Block,
+        deadline: Option<Instant>,
+        mut failed: Option<anyhow::Error>,
+    ) -> Result<Vec<u8>> {
+        let mut hash_mismatches = 0;
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let result = match (failed.take(), deadline) {
+                (Some(err), _) => Err(err),
+                (None, Some(deadline)) => {
+                    match tokio::time::timeout_at(deadline, self.attempt(block)).await {
+                        Ok(result) => result,
+                        Err(_) => {
+                            return Err(AcquireError::Unavailable(format!(
+                                "Deadline for block {} exceeded during attempt {}",
+                                block.hash, attempt
+                            ))
+                            .into())
+                        }
+                    }
+                }
+                (None, None) => self.attempt(block).await,
+            };
+            let err = match result {
+                Ok(data) => return Ok(data),
+                Err(err) => err,
+            };
+            if let Some(AcquireError::InvalidData(_)) = err.downcast_ref::<AcquireError>() {
+                hash_mismatches += 1;
+                if hash_mismatches >= self.policy.max_hash_mismatches {
+                    return Err(err.context(format!(
+                        "Block {} arrived with the wrong hash {} times",
+                        block.hash, hash_mismatches
+                    )));
+                }
+            }
+            if !is_retryable(&err) || attempt >= self.policy.max_attempts {
+                return Err(err.context(format!(
+                    "Giving up on block {} after {} attempts",
+                    block.hash, attempt
+                )));
+            }
+            let backoff = self.policy.backoff(attempt);
+            if let Some(deadline) = deadline {
+                if Instant::now() + backoff >= deadline {
+                    return Err(err.context(format!("Deadline for block {} exceeded", block.hash)));
+                }
+            }
+            tokio::time::sleep(backoff).await;
+        }
+    }
+    async fn attempt(&self, block: &Block) -> Result<Vec<u8>> {
+        let data = match self.policy.attempt_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self.inner.get_block(block))
+                .await
+                .map_err(|_| {
+                    AcquireError::Unavailable(format!("Request for block {} timed out", block.hash))
+                })??,
+            None => self.inner.get_block(block).await?,
+        };
+        if block.validate(&data).is_err() {
+            return Err(AcquireError::InvalidData(block.hash.clone()).into());
+        }
+        Ok(data)
+    }
+}
+
+#[async_trait]
+impl<A: Acquirer> Acquirer for RetryingAcquirer<A> {
+    async fn get_block(&self, block: &Block) -> Result<Vec<u8>> {
+        let deadline = self
+            .policy
+            .deadline
+            .map(|deadline| Instant::now() + deadline);
+        self.retry(block, deadline, None).await
+    }
+    /// Requests the batch from the inner acquirer as the first attempt of every block, and retries
+    /// the blocks that failed one by one once it is over. The attempt timeout and the deadline
+    /// limit the batch as a whole, blocks that haven't arrived by then are retried too.
+    fn get_blocks<'a>(&'a self, blocks: &'a [Block]) -> BoxStream<'a, (usize, Result<Vec<u8>>)> {
+        let started = Instant::now();
+        let deadline = self.policy.deadline.map(|deadline| started + deadline);
+        let batch_deadline = self
+            .policy
+            .attempt_timeout
+            .map(|timeout| started + timeout)
+            .into_iter()
+            .chain(deadline)
+            .min();
+        let batch = match batch_deadline {
+            Some(at) => self
+                .inner
+                .get_blocks(blocks)
+                .take_until(tokio::time::sleep_until(at))
+                .boxed(),
+            None => self.inner.get_blocks(blocks),
+        };
+        // Failed blocks wait for the end of the batch, so they don't hold up the blocks behind them.
+        // Each block is unanswered (None), answered (Some(None)) or failed (Some(Some(err))).
+        let failed = Arc::new(Mutex::new(
+            blocks.iter().map(|_| None).collect::<Vec<Option<_>>>(),
+        ));
+        let answered = failed.clone();
+        let batch = batch.filter_map(move |(index, result)| {
+            let mut failed = answered.lock().unwrap();
+            let result = match (failed.get_mut(index), result) {
+                (None, result) => Some((index, result)),
+                // Only the first result of a block counts
+                (Some(Some(_)), _) => None,
+                (Some(slot), Ok(data)) if blocks[index].validate(&data).is_ok() => {
+                    *slot = Some(None);
+                    Some((index, Ok(data)))
+                }
+                (Some(slot), Ok(_)) => {
+                    *slot = Some(Some(
+                        AcquireError::InvalidData(blocks[index].hash.clone()).into(),
+                    ));
+                    None
+                }
+                (Some(slot), Err(err)) if is_retryable(&err) => {
+                    *slot = Some(Some(err));
+                    None
+                }
+                (Some(slot), Err(err)) => {
+                    *slot = Some(None);
+                    Some((index, Err(err)))
+                }
+            };
+            futures::future::ready(result)
+        });
+        let retries = futures::stream::once(async move {
+            let failed = std::mem::take(&mut *failed.lock().unwrap());
+            futures::stream::iter(failed.into_iter().enumerate().filter_map(|(index, slot)| {
+                match slot {
+                    // Answered in the batch
+                    Some(None) => None,
+                    Some(Some(err)) => Some((index, err)),
+                    None => Some((
+                        index,
+                        AcquireError::Unavailable(format!(
+                            "Batch request returned nothing for block {}",
+                            blocks[index].hash
+                        ))
+                        .into(),
+                    )),
+                }
+            }))
+        })
+        .flatten()
+        .then(move |(index, err)| async move {
+            (index, self.retry(&blocks[index], deadline, Some(err)).await)
+        });
+        batch.chain(retries).boxed()
+    }
+    fn set_remaining(&self, remaining: usize) {
+        self.inner.set_remaining(remaining)
+    }
+}
@@ -0,0 +1,31 @@
+use super::{AcquireError, Acquirer};
+use crate::{block::Block, storage::Storage};
+use anyhow::Result;
+use async_trait::async_trait;
+
+/// Serves blocks out of a storage, so one storage can be filled from another through a `Downloader`.
+pub struct StorageAcquirer<S: Storage> {
+    storage: S,
+}
+
+impl<S: Storage> StorageAcquirer<S> {
+    pub fn new(storage: S) -> Self {
+        Self { storage }
+    }
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+    pub fn into_storage(self) -> S {
+        self.storage
+    }
+}
+
+#[async_trait]
+impl<S: Storage> Acquirer for StorageAcquirer<S> {
+    async fn get_block(&self, block: &Block) -> Result<Vec<u8>> {
+        match self.storage.get_block_data(block).await? {
+            Some(data) => Ok(data),
+            None => Err(AcquireError::NotFound(block.hash.clone()).into()),
+        }
+    }
+}
@@ -40,3 +40,13 @@ impl Block {
         }
     }
 }
+
+/// Whether the string is a hash the way blocks and files are addressed: 64 lowercase hexadecimal
+/// characters. Hashes received from a client need to pass this before they reach a storage, which
+/// may use them as a path.
+pub fn is_valid_hash(hash: &str) -> bool {
+    hash.len() == 64
+        && hash
+            .bytes()
+            .all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
+}
@@ -0,0 +1,350 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::Arc,
+};
+
+use crate::{
+    acquirer::Acquirer,
+    block::Block,
+    file::File,
+    progress::{Progress, ProgressTracker},
+    schedule::{PendingBlock, Scheduler, SequentialScheduler},
+    storage::Storage,
+};
+use anyhow::{anyhow, Result};
+use futures::{stream, StreamExt};
+use tokio::sync::{mpsc, watch};
+
+/// Outcome of a download, split by what happened to each unique block of the file.
+#[derive(Debug, Default)]
+pub struct DownloadReport {
+    /// Blocks that were acquired, validated and stored.
+    pub fetched: Vec<Block>,
+    /// Blocks that were already present in the storage.
+    pub skipped: Vec<Block>,
+    /// Blocks that could not be acquired or failed validation.
+    pub failed: Vec<(Block, anyhow::Error)>,
+    /// The download was cancelled before all blocks were requested.
+    pub cancelled: bool,
+}
+
+impl DownloadReport {
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty() && !self.cancelled
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadState {
+    Running,
+    Paused,
+    Cancelled,
+}
+
+/// Controls and follows one download from anywhere, clones control the same download.
+#[derive(Clone)]
+pub struct DownloadHandle {
+    state: Arc<watch::Sender<DownloadState>>,
+    progress: Arc<ProgressTracker>,
+}
+
+impl DownloadHandle {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(watch::channel(DownloadState::Running).0),
+            progress: Arc::new(ProgressTracker::new()),
+        }
+    }
+    pub fn state(&self) -> DownloadState {
+        *self.state.borrow()
+    }
+    /// Follows the progress of the download. A handle is meant for one download at a time, the
+    /// totals start over whenever a download is started with it.
+    pub fn progress(&self) -> watch::Receiver<Progress> {
+        self.progress.subscribe()
+    }
+    /// Stops sending requests and storing blocks until the download is resumed. Requests
+    /// already in flight are suspended, not cancelled.
+    pub fn pause(&self) {
+        self.transition(DownloadState::Running, DownloadState::Paused);
+    }
+    pub fn resume(&self) {
+        self.transition(DownloadState::Paused, DownloadState::Running);
+    }
+    /// Stops the download for good, dropping the requests that are in flight. Blocks are stored
+    /// whole or not at all, so a later download of the same file picks up where this one stopped.
+    pub fn cancel(&self) {
+        self.state.send_replace(DownloadState::Cancelled);
+    }
+
+    fn transition(&self, from: DownloadState, to: DownloadState) {
+        self.state.send_if_modified(|state| {
+            let modified = *state == from;
+            if modified {
+                *state = to;
+            }
+            modified
+        });
+    }
+    /// Resolves once the download isn't paused anymore, with the new state.
+    async fn unpaused(&self) -> DownloadState {
+        let mut state = self.state.subscribe();
+        let state = state
+            .wait_for(|state| *state != DownloadState::Paused)
+            .await
+            .expect("The sender is owned by the handle");
+        *state
+    }
+    /// Resolves once the download is paused or cancelled.
+    async fn interrupted(&self) {
+        let mut state = self.state.subscribe();
+        let _ = state
+            .wait_for(|state| *state != DownloadState::Running)
+            .await;
+    }
+}
+
+impl Default for DownloadHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What happened to a block during a download.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BlockOutcome {
+    Skipped,
+    Fetched,
+    Failed,
+}
+
+/// Completes files in a storage by requesting their missing blocks from an acquirer.
+pub struct Downloader<A: Acquirer> {
+    acquirer: A,
+    concurrency: usize,
+    batch_size: usize,
+    scheduler: Box<dyn Scheduler>,
+}
+
+impl<A: Acquirer> Downloader<A> {
+    pub fn new(acquirer: A) -> Self {
+        Self {
+            acquirer,
+            concurrency: 1,
+            batch_size: 1,
+            scheduler: Box::new(SequentialScheduler),
+        }
+    }
+    /// Sets how many block requests may be in flight at once. Blocks are still written to the
+    /// storage one at a time, in the order they were requested.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+    /// Sets how many blocks are requested together through `Acquirer::get_blocks`, which
+    /// transports can use to serve several blocks per round trip. Each batch counts as one
+    /// request towards the concurrency limit.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+    /// Sets the order in which missing blocks are requested, blocks are requested in file order by default.
+    pub fn with_scheduler<S: Scheduler + 'static>(mut self, scheduler: S) -> Self {
+        self.scheduler = Box::new(scheduler);
+        self
+    }
+    pub fn acquirer(&self) -> &A {
+        &self.acquirer
+    }
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+    /// Fetches every block of the file that is not yet in the storage. Blocks that cannot be
+    /// acquired or don't match their hash are recorded in the report, storage errors abort the download.
+    pub async fn download<S: Storage>(
+        &self,
+        file: &File,
+        storage: &mut S,
+    ) -> Result<DownloadReport> {
+        self.download_with_handle(file, storage, &DownloadHandle::new())
+            .await
+    }
+    /// Like `download`, but can be followed, paused, resumed and cancelled through the handle.
+    pub async fn download_with_handle<S: Storage>(
+        &self,
+        file: &File,
+        storage: &mut S,
+        handle: &DownloadHandle,
+    ) -> Result<DownloadReport> {
+        self.download_blocks(&[&file.blocks], storage, handle, |_, _| {})
+            .await
+    }
+    /// Downloads the unique blocks of the files, given as their lists of blocks, reporting what
+    /// happened to each of them.
+    pub(crate) async fn download_blocks<S, F>(
+        &self,
+        files: &[&[Block]],
+        storage: &mut S,
+        handle: &DownloadHandle,
+        mut on_block: F,
+    ) -> Result<DownloadReport>
+    where
+        S: Storage,
+        F: FnMut(&Block, BlockOutcome) + Send,
+    {
+        let mut report = DownloadReport::default();
+        let mut seen = HashSet::new();
+        let mut unique = Vec::new();
+        let mut missing = Vec::new();
+        for (file, blocks) in files.iter().enumerate() {
+            let mut offset = 0;
+            for (index, block) in blocks.iter().enumerate() {
+                let block_offset = offset;
+                offset += block.length;
+                if !seen.insert(block.hash.clone()) {
+                    continue;
+                }
+                unique.push(block.clone());
+                if storage.block_exists(block).await? {
+                    report.skipped.push(block.clone());
+                } else {
+                    missing.push(PendingBlock {
+                        file,
+                        index,
+                        offset: block_offset,
+                        block: block.clone(),
+                    });
+                }
+            }
+        }
+        handle.progress.reset(&unique, &report.skipped);
+        // Reported after the reset, so callbacks reading the handle see totals that include them
+        for block in &report.skipped {
+            on_block(block, BlockOutcome::Skipped);
+        }
+
+        let mut remaining = missing.len();
+        self.acquirer.set_remaining(remaining);
+        // The scheduler picks the next blocks only when a request slot is free
+        let mut pending = missing;
+        let mut requested = 0;
+        let scheduled = std::iter::from_fn(move || {
+            let mut batch = Vec::new();
+            while !pending.is_empty() && batch.len() < self.batch_size {
+                let next = self.scheduler.next(&pending);
+                if next >= pending.len() {
+                    let len = pending.len();
+                    pending.clear();
+                    return Some(Err(anyhow!(
+                        "Scheduler picked position {} but only {} blocks are pending",
+                        next,
+                        len
+                    )));
+                }
+                batch.push((requested, pending.swap_remove(next).block));
+                requested += 1;
+            }
+            (!batch.is_empty()).then_some(Ok(batch))
+        });
+        // Batches hand over their blocks one by one as they arrive, numbered in the order they were
+        // requested so they can be stored in that order
+        let (sender, mut receiver) = mpsc::channel(self.concurrency);
+        let mut fetches = stream::iter(scheduled)
+            .map(|batch| {
+                let sender = sender.clone();
+                async move {
+                    let (sequence, batch): (Vec<_>, Vec<_>) = batch?.into_iter().unzip();
+                    for block in &batch {
+                        handle.progress.started(block);
+                    }
+                    let mut results = if let [block] = batch.as_slice() {
+                        stream::once(async move { (0, self.acquirer.get_block(block).await) })
+                            .boxed()
+                    } else {
+                        self.acquirer.get_blocks(&batch)
+                    };
+                    let mut answered = vec![false; batch.len()];
+                    while let Some((index, result)) = results.next().await {
+                        if answered.get(index) != Some(&false) {
+                            continue;
+                        }
+                        answered[index] = true;
+                        if let Ok(data) = &result {
+                            handle.progress.received(&batch[index], data.len() as u64);
+                        }
+                        let fetched = (sequence[index], batch[index].clone(), result);
+                        if sender.send(fetched).await.is_err() {
+                            return Ok(());
+                        }
+                    }
+                    drop(results);
+                    for (index, block) in batch.iter().enumerate() {
+                        if !answered[index] {
+                            let err = anyhow!("Acquirer returned nothing for block {}", block.hash);
+                            let _ = sender
+                                .send((sequence[index], block.clone(), Err(err)))
+                                .await;
+                        }
+                    }
+                    Ok::<_, anyhow::Error>(())
+                }
+            })
+            .buffered(self.concurrency);
+        let mut fetching = true;
+        // Blocks that arrived before the blocks requested ahead of them
+        let mut arrived = BTreeMap::new();
+        let mut next_write = 0;
+        loop {
+            if handle.unpaused().await == DownloadState::Cancelled {
+                report.cancelled = true;
+                break;
+            }
+            let (block, result) = match arrived.remove(&next_write) {
+                Some(fetched) => fetched,
+                None => {
+                    let (sequence, block, result) = if fetching {
+                        tokio::select! {
+                            Some(fetched) = receiver.recv() => fetched,
+                            next = fetches.next() => {
+                                match next {
+                                    Some(fetched) => fetched?,
+                                    None => fetching = false,
+                                }
+                                continue;
+                            }
+                            _ = handle.interrupted() => continue,
+                        }
+                    } else {
+                        // Every batch is over, only what they already sent is left
+                        match receiver.try_recv() {
+                            Ok(fetched) => fetched,
+                            Err(_) => break,
+                        }
+                    };
+                    arrived.insert(sequence, (block, result));
+                    continue;
+                }
+            };
+            next_write += 1;
+            remaining -= 1;
+            self.acquirer.set_remaining(remaining);
+            let data = match result.and_then(|data| block.validate(&data).map(|_| data)) {
+                Ok(data) => data,
+                Err(err) => {
+                    handle.progress.failed(&block, &err);
+                    on_block(&block, BlockOutcome::Failed);
+                    report.failed.push((block, err));
+                    continue;
+                }
+            };
+            storage.upsert_block_data(&block, data).await?;
+            handle.progress.completed(&block);
+            on_block(&block, BlockOutcome::Fetched);
+            report.fetched.push(block);
+        }
+        Ok(report)
+    }
+}
@@ -1,12 +1,16 @@
 use crate::{
     block::Block,
-    crypto::{parse_public_key, KeyPair, PublicKey},
+    crypto::{KeyPair, PublicKey},
     storage::Storage,
 };
-use anyhow::{Context, Result};
+use anyhow::Result;
 use blake3::Hash;
 use serde::{Deserialize, Serialize};
 
+/// Leads the signed payload, so a signature made over a different payload, like the bare file hash
+/// signed by earlier versions, can never verify as one over the block list.
+const SIGNATURE_VERSION: &[u8] = b"incremental-file signature v2";
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct File {
     pub blocks: Vec<Block>,
@@ -29,10 +33,9 @@ impl File {
     ) -> Result<Self> {
         let data = data.as_ref();
         let mut blocks = Vec::new();
-        let mut data_iter = data.iter();
         let mut block_data = Vec::new();
         let mut block_length = 0;
-        while let Some(byte) = data_iter.next() {
+        for byte in data {
             block_data.push(*byte);
             block_length += 1;
             if block_length == block_size {
@@ -43,7 +46,7 @@ impl File {
                 block_length = 0;
             }
         }
-        if block_data.len() > 0 {
+        if !block_data.is_empty() {
             let block = Block::from_data(&block_data);
             blocks.push(block.clone());
             storage.upsert_block_data(&block, &block_data).await?;
@@ -55,12 +58,27 @@ impl File {
             signature: None,
         })
     }
+    /// Signs the hash of the file together with its ordered list of blocks, so neither can be
+    /// swapped out under a valid signature.
     pub fn sign(&mut self, keypair: &KeyPair) -> Result<()> {
-        let hash = self.hash()?;
-        let signature = keypair.sign(hash.as_bytes());
+        let digest = self.signed_digest()?;
+        let signature = keypair.sign(digest.as_bytes());
         self.signature = Some(hex::encode(signature));
         Ok(())
     }
+    /// Digest over the payload version, the file hash and the length and hash of every block, in order.
+    fn signed_digest(&self) -> Result<Hash> {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(SIGNATURE_VERSION);
+        hasher.update(self.hash()?.as_bytes());
+        hasher.update(&(self.blocks.len() as u64).to_le_bytes());
+        for block in &self.blocks {
+            let hash: Hash = block.hash.parse()?;
+            hasher.update(&block.length.to_le_bytes());
+            hasher.update(hash.as_bytes());
+        }
+        Ok(hasher.finalize())
+    }
     pub fn hash(&self) -> Result<Hash> {
         let hash = self.hash.parse()?;
         Ok(hash)
@@ -70,7 +88,7 @@ impl File {
         for block in &self.blocks {
             data.extend(
                 storage
-                    .get_block_data(&block)
+                    .get_block_data(block)
                     .await?
                     .expect("Block data is empty"),
             );
@@ -93,41 +111,49 @@ impl File {
         storage: &S,
         public_key: &PublicKey,
     ) -> Result<()> {
-        let hash = self.hash()?;
-        let data = self.data(storage).await?;
-        let hash_data = blake3::hash(data.as_slice());
-        // Validate hash
-        if hash != hash_data {
-            Err(anyhow::anyhow!("Invalid hash"))
-        } else {
-            // Validate signature
-            if let Some(signature) = &self.signature {
-                let signature = hex::decode(signature)?;
-                if let Err(verify_err) = public_key.verify(hash.as_bytes(), &signature) {
-                    Err(anyhow::anyhow!(format!(
-                        "Signature {:?} is invalid: {}",
-                        signature, verify_err
-                    )))
-                } else {
-                    Ok(())
-                }
+        self.validate(storage).await?;
+        self.verify(public_key)
+    }
+    /// Checks the signature of the file hash and its list of blocks without looking at any data,
+    /// so a file received from elsewhere can be trusted before its blocks are downloaded. Blocks
+    /// are validated against their hash as they arrive, and their list is covered by the signature.
+    pub fn verify(&self, public_key: &PublicKey) -> Result<()> {
+        let digest = self.signed_digest()?;
+        if let Some(signature) = &self.signature {
+            let signature = hex::decode(signature)?;
+            if let Err(verify_err) = public_key.verify(digest.as_bytes(), &signature) {
+                Err(anyhow::anyhow!(format!(
+                    "Signature {:?} is invalid: {}",
+                    signature, verify_err
+                )))
             } else {
-                Err(anyhow::anyhow!("No signature"))
+                Ok(())
             }
+        } else {
+            Err(anyhow::anyhow!("No signature"))
         }
     }
+    /// Blocks whose data isn't in the storage yet.
     pub async fn unfinished_blocks<S: Storage>(&self, storage: &S) -> Result<Vec<Block>> {
         let mut unfinished_blocks = Vec::new();
         for block in &self.blocks {
-            if storage.block_exists(&block).await? {
+            if !storage.block_exists(block).await? {
                 unfinished_blocks.push(block.clone());
             }
         }
         Ok(unfinished_blocks)
     }
+    /// Share of the file's bytes that are in the storage, between 0.0 and 1.0.
     pub async fn progress<S: Storage>(&self, storage: &S) -> Result<f64> {
+        let total = self.blocks.iter().map(|block| block.length).sum::<u64>();
+        if total == 0 {
+            return Ok(1.0);
+        }
         let unfinished_blocks = self.unfinished_blocks(storage).await?;
-        let progress = unfinished_blocks.len() as f64 / self.blocks.len() as f64;
-        Ok(progress)
+        let unfinished = unfinished_blocks
+            .iter()
+            .map(|block| block.length)
+            .sum::<u64>();
+        Ok((total - unfinished) as f64 / total as f64)
     }
 }
@@ -2,5 +2,10 @@ pub mod acquirer;
 pub mod block;
 pub mod converter;
 pub mod crypto;
+pub mod downloader;
 pub mod file;
+pub mod limit;
+pub mod progress;
+pub mod queue;
+pub mod schedule;
 pub mod storage;
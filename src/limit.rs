@@ -0,0 +1,227 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{acquirer::Acquirer, block::Block, file::File, storage::Storage};
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::{stream::BoxStream, StreamExt};
+use tokio::time::Instant;
+
+/// Longest a waiting caller sleeps before looking at the bucket again, so rate changes apply quickly.
+const MAX_WAIT: Duration = Duration::from_millis(100);
+
+struct Bucket {
+    bytes_per_second: u64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// Bursts are limited to a tenth of a second worth of bytes.
+    fn capacity(&self) -> f64 {
+        self.bytes_per_second as f64 / 10.0
+    }
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.bytes_per_second as f64).min(self.capacity());
+        self.updated = now;
+    }
+}
+
+/// A token bucket measured in bytes per second. Clones share the same bucket, so one limiter can
+/// cap the combined rate of any number of acquirers, storages and concurrent requests.
+#[derive(Clone)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+impl RateLimiter {
+    /// A rate of 0 means unlimited.
+    pub fn new(bytes_per_second: u64) -> Self {
+        let bucket = Bucket {
+            bytes_per_second,
+            tokens: 0.0,
+            updated: Instant::now(),
+        };
+        Self {
+            bucket: Arc::new(Mutex::new(bucket)),
+        }
+    }
+    pub fn rate(&self) -> u64 {
+        self.bucket.lock().unwrap().bytes_per_second
+    }
+    /// Changes the rate for every user of the limiter, including callers that are already waiting.
+    pub fn set_rate(&self, bytes_per_second: u64) {
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.refill();
+        bucket.bytes_per_second = bytes_per_second;
+        bucket.tokens = bucket.tokens.min(bucket.capacity());
+    }
+    /// Waits until the given amount of bytes may be transferred. Transfers larger than the bucket
+    /// are let through once it isn't in debt, and the following callers wait until the debt is paid.
+    pub async fn acquire(&self, bytes: u64) {
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().unwrap();
+                if bucket.bytes_per_second == 0 {
+                    return;
+                }
+                bucket.refill();
+                if bucket.tokens >= 0.0 {
+                    bucket.tokens -= bytes as f64;
+                    return;
+                }
+                Duration::from_secs_f64(-bucket.tokens / bucket.bytes_per_second as f64)
+            };
+            tokio::time::sleep(wait.min(MAX_WAIT)).await;
+        }
+    }
+    /// Gives back bytes that were acquired but not transferred, the bucket still holds at most
+    /// its capacity.
+    pub fn release(&self, bytes: u64) {
+        let mut bucket = self.bucket.lock().unwrap();
+        if bucket.bytes_per_second == 0 {
+            return;
+        }
+        bucket.refill();
+        bucket.tokens = (bucket.tokens + bytes as f64).min(bucket.capacity());
+    }
+}
+
+/// Bytes acquired ahead of the transfer of some blocks. Each block is settled once against what
+/// actually arrived, and whatever is left unsettled is released when the reservation is dropped.
+struct Reservation<'a> {
+    limiter: &'a RateLimiter,
+    lengths: Vec<u64>,
+}
+
+impl<'a> Reservation<'a> {
+    async fn acquire(limiter: &'a RateLimiter, blocks: &[Block]) -> Reservation<'a> {
+        let lengths = blocks.iter().map(|block| block.length).collect::<Vec<_>>();
+        limiter.acquire(lengths.iter().sum()).await;
+        Self { limiter, lengths }
+    }
+    /// Releases what the block at `index` reserved beyond the bytes received, and returns how many
+    /// bytes were received beyond its reservation, which still have to be acquired.
+    fn settle(&mut self, index: usize, received: u64) -> u64 {
+        let reserved = self.lengths.get_mut(index).map_or(0, std::mem::take);
+        self.limiter.release(reserved.saturating_sub(received));
+        received.saturating_sub(reserved)
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        self.limiter.release(self.lengths.iter().sum());
+    }
+}
+
+/// Limits the rate at which block data is received from the inner acquirer. Requests reserve the
+/// length of their blocks before they are sent and settle it against the bytes they actually
+/// received, so a failed request or a block shorter than its metadata claims gives the rest back.
+pub struct RateLimitedAcquirer<A: Acquirer> {
+    inner: A,
+    limiter: RateLimiter,
+}
+
+impl<A: Acquirer> RateLimitedAcquirer<A> {
+    pub fn new(inner: A, limiter: RateLimiter) -> Self {
+        Self { inner, limiter }
+    }
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+    pub fn limiter(&self) -> &RateLimiter {
+        &self.limiter
+    }
+}
+
+#[async_trait]
+impl<A: Acquirer> Acquirer for RateLimitedAcquirer<A> {
+    async fn get_block(&self, block: &Block) -> Result<Vec<u8>> {
+        let mut reservation =
+            Reservation::acquire(&self.limiter, std::slice::from_ref(block)).await;
+        let data = self.inner.get_block(block).await?;
+        let excess = reservation.settle(0, data.len() as u64);
+        if excess > 0 {
+            self.limiter.acquire(excess).await;
+        }
+        Ok(data)
+    }
+    fn get_blocks<'a>(&'a self, blocks: &'a [Block]) -> BoxStream<'a, (usize, Result<Vec<u8>>)> {
+        futures::stream::once(Reservation::acquire(&self.limiter, blocks))
+            .flat_map(move |mut reservation| {
+                self.inner.get_blocks(blocks).then(move |(index, result)| {
+                    let received = result.as_ref().map_or(0, |data| data.len() as u64);
+                    let excess = reservation.settle(index, received);
+                    async move {
+                        if excess > 0 {
+                            self.limiter.acquire(excess).await;
+                        }
+                        (index, result)
+                    }
+                })
+            })
+            .boxed()
+    }
+    fn set_remaining(&self, remaining: usize) {
+        self.inner.set_remaining(remaining)
+    }
+}
+
+/// Limits the rate at which block data is written to the inner storage. Reads aren't limited.
+pub struct RateLimitedStorage<S: Storage> {
+    inner: S,
+    limiter: RateLimiter,
+}
+
+impl<S: Storage> RateLimitedStorage<S> {
+    pub fn new(inner: S, limiter: RateLimiter) -> Self {
+        Self { inner, limiter }
+    }
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+    pub fn limiter(&self) -> &RateLimiter {
+        &self.limiter
+    }
+}
+
+#[async_trait]
+impl<S: Storage> Storage for RateLimitedStorage<S> {
+    async fn get_file(&self, hash: &str) -> Result<Option<File>> {
+        self.inner.get_file(hash).await
+    }
+    async fn file_exists(&self, hash: &str) -> Result<bool> {
+        self.inner.file_exists(hash).await
+    }
+    async fn upsert_file(&mut self, file: &File) -> Result<()> {
+        self.inner.upsert_file(file).await
+    }
+    async fn remove_file(&mut self, hash: &str) -> Result<()> {
+        self.inner.remove_file(hash).await
+    }
+    async fn get_block_data(&self, block: &Block) -> Result<Option<Vec<u8>>> {
+        self.inner.get_block_data(block).await
+    }
+    async fn block_exists(&self, block: &Block) -> Result<bool> {
+        self.inner.block_exists(block).await
+    }
+    async fn upsert_block_data<D: AsRef<[u8]> + Send>(
+        &mut self,
+        block: &Block,
+        data: D,
+    ) -> Result<()> {
+        self.limiter.acquire(data.as_ref().len() as u64).await;
+        self.inner.upsert_block_data(block, data).await
+    }
+    async fn remove_block_data(&mut self, block: &Block) -> Result<()> {
+        self.inner.remove_block_data(block).await
+    }
+}
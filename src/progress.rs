@@ -0,0 +1,138 @@
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::block::Block;
+use tokio::sync::watch;
+
+/// The rate is averaged over the transfers of this last stretch of time.
+const RATE_WINDOW: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+pub enum ProgressEvent {
+    BlockStarted(Block),
+    BlockCompleted(Block),
+    /// The block and a description of the error.
+    BlockFailed(Block, String),
+    /// Bytes of the block were transferred from the acquirer. Acquirers hand over whole blocks, so
+    /// the transfer is reported once per block with its length, before the data is validated.
+    BytesTransferred(Block, u64),
+}
+
+/// A snapshot of a download. Receivers that fall behind only see the latest event, but the totals
+/// always account for every event.
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    pub event: Option<ProgressEvent>,
+    pub total_blocks: usize,
+    pub completed_blocks: usize,
+    pub failed_blocks: usize,
+    pub total_bytes: u64,
+    pub completed_bytes: u64,
+    /// Bytes received from the acquirer, including blocks that failed validation afterwards.
+    pub received_bytes: u64,
+    /// Bytes transferred per second, averaged over the last few seconds.
+    pub bytes_per_second: f64,
+    pub eta: Option<Duration>,
+}
+
+impl Progress {
+    /// Completed share of the bytes, between 0.0 and 1.0.
+    pub fn fraction(&self) -> f64 {
+        if self.total_bytes == 0 {
+            1.0
+        } else {
+            self.completed_bytes as f64 / self.total_bytes as f64
+        }
+    }
+    pub fn is_finished(&self) -> bool {
+        self.completed_blocks + self.failed_blocks >= self.total_blocks
+    }
+}
+
+/// Keeps a `Progress` up to date and publishes it on a watch channel.
+pub(crate) struct ProgressTracker {
+    sender: watch::Sender<Progress>,
+    transfers: Mutex<VecDeque<(Instant, u64)>>,
+}
+
+impl ProgressTracker {
+    pub(crate) fn new() -> Self {
+        Self {
+            sender: watch::channel(Progress::default()).0,
+            transfers: Mutex::new(VecDeque::new()),
+        }
+    }
+    pub(crate) fn subscribe(&self) -> watch::Receiver<Progress> {
+        self.sender.subscribe()
+    }
+    /// Starts a new download, with the blocks that were already present counted as completed.
+    pub(crate) fn reset(&self, total: &[Block], completed: &[Block]) {
+        self.transfers.lock().unwrap().clear();
+        self.sender.send_replace(Progress {
+            total_blocks: total.len(),
+            completed_blocks: completed.len(),
+            total_bytes: total.iter().map(|block| block.length).sum(),
+            completed_bytes: completed.iter().map(|block| block.length).sum(),
+            ..Progress::default()
+        });
+    }
+    pub(crate) fn started(&self, block: &Block) {
+        self.sender.send_modify(|progress| {
+            progress.event = Some(ProgressEvent::BlockStarted(block.clone()));
+        });
+    }
+    pub(crate) fn received(&self, block: &Block, bytes: u64) {
+        let now = Instant::now();
+        let bytes_per_second = {
+            let mut transfers = self.transfers.lock().unwrap();
+            transfers.push_back((now, bytes));
+            while let Some((time, _)) = transfers.front() {
+                if now.duration_since(*time) > RATE_WINDOW {
+                    transfers.pop_front();
+                } else {
+                    break;
+                }
+            }
+            let window = transfers
+                .front()
+                .map(|(time, _)| now.duration_since(*time))
+                .unwrap_or_default()
+                .max(Duration::from_millis(100));
+            let bytes = transfers.iter().map(|(_, bytes)| bytes).sum::<u64>();
+            bytes as f64 / window.as_secs_f64()
+        };
+        self.sender.send_modify(|progress| {
+            progress.event = Some(ProgressEvent::BytesTransferred(block.clone(), bytes));
+            progress.received_bytes += bytes;
+            progress.bytes_per_second = bytes_per_second;
+        });
+    }
+    pub(crate) fn completed(&self, block: &Block) {
+        self.sender.send_modify(|progress| {
+            progress.event = Some(ProgressEvent::BlockCompleted(block.clone()));
+            progress.completed_blocks += 1;
+            progress.completed_bytes += block.length;
+            let remaining = progress
+                .total_bytes
+                .saturating_sub(progress.completed_bytes);
+            progress.eta = if remaining == 0 {
+                Some(Duration::ZERO)
+            } else if progress.bytes_per_second > 0.0 {
+                Some(Duration::from_secs_f64(
+                    remaining as f64 / progress.bytes_per_second,
+                ))
+            } else {
+                None
+            };
+        });
+    }
+    pub(crate) fn failed(&self, block: &Block, err: &anyhow::Error) {
+        self.sender.send_modify(|progress| {
+            progress.event = Some(ProgressEvent::BlockFailed(block.clone(), err.to_string()));
+            progress.failed_blocks += 1;
+        });
+    }
+}
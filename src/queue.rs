@@ -0,0 +1,166 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    acquirer::Acquirer,
+    block::Block,
+    downloader::{BlockOutcome, DownloadHandle, DownloadReport, Downloader},
+    file::File,
+    progress::Progress,
+    storage::Storage,
+};
+use anyhow::Result;
+use tokio::sync::watch;
+
+/// Progress of one file of a queue, counting each of its unique blocks once.
+#[derive(Debug, Clone, Default)]
+pub struct FileProgress {
+    pub hash: String,
+    pub total_blocks: usize,
+    pub completed_blocks: usize,
+    pub failed_blocks: usize,
+    pub total_bytes: u64,
+    pub completed_bytes: u64,
+}
+
+impl FileProgress {
+    /// Completed share of the bytes, between 0.0 and 1.0.
+    pub fn fraction(&self) -> f64 {
+        if self.total_bytes == 0 {
+            1.0
+        } else {
+            self.completed_bytes as f64 / self.total_bytes as f64
+        }
+    }
+    pub fn is_complete(&self) -> bool {
+        self.completed_blocks == self.total_blocks
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct QueueProgress {
+    pub files: Vec<FileProgress>,
+    /// Progress over the unique blocks of all files together.
+    pub overall: Progress,
+}
+
+#[derive(Debug, Default)]
+pub struct QueueReport {
+    /// What happened to every unique block of all files together.
+    pub blocks: DownloadReport,
+    pub complete_files: Vec<String>,
+    pub incomplete_files: Vec<String>,
+}
+
+impl QueueReport {
+    pub fn is_complete(&self) -> bool {
+        self.incomplete_files.is_empty() && self.blocks.is_complete()
+    }
+}
+
+/// Downloads many files into one storage. Files are content addressed by block, so a block that
+/// several files share is requested once and counts towards the progress of each of them.
+pub struct DownloadQueue<A: Acquirer> {
+    downloader: Downloader<A>,
+    files: Vec<File>,
+    progress: watch::Sender<QueueProgress>,
+}
+
+impl<A: Acquirer> DownloadQueue<A> {
+    pub fn new(downloader: Downloader<A>) -> Self {
+        Self {
+            downloader,
+            files: Vec::new(),
+            progress: watch::channel(QueueProgress::default()).0,
+        }
+    }
+    pub fn push(&mut self, file: File) {
+        self.files.push(file);
+    }
+    pub fn files(&self) -> &[File] {
+        &self.files
+    }
+    pub fn downloader(&self) -> &Downloader<A> {
+        &self.downloader
+    }
+    pub fn progress(&self) -> watch::Receiver<QueueProgress> {
+        self.progress.subscribe()
+    }
+    pub async fn download<S: Storage>(&self, storage: &mut S) -> Result<QueueReport> {
+        self.download_with_handle(storage, &DownloadHandle::new())
+            .await
+    }
+    /// Like `download`, but can be paused, resumed and cancelled through the handle. The handle
+    /// follows the overall progress, `progress` breaks it down by file.
+    pub async fn download_with_handle<S: Storage>(
+        &self,
+        storage: &mut S,
+        handle: &DownloadHandle,
+    ) -> Result<QueueReport> {
+        let mut files = Vec::new();
+        let mut files_by_block: HashMap<String, Vec<usize>> = HashMap::new();
+        for (index, file) in self.files.iter().enumerate() {
+            let mut progress = FileProgress {
+                hash: file.hash.clone(),
+                ..FileProgress::default()
+            };
+            let mut seen = HashSet::new();
+            for block in &file.blocks {
+                if seen.insert(&block.hash) {
+                    progress.total_blocks += 1;
+                    progress.total_bytes += block.length;
+                    files_by_block
+                        .entry(block.hash.clone())
+                        .or_default()
+                        .push(index);
+                }
+            }
+            files.push(progress);
+        }
+        self.progress.send_replace(QueueProgress {
+            files,
+            overall: Progress::default(),
+        });
+
+        // All files are downloaded together, the downloader skips repeated hashes
+        let blocks = self
+            .files
+            .iter()
+            .map(|file| file.blocks.as_slice())
+            .collect::<Vec<&[Block]>>();
+        let overall = handle.progress();
+        let blocks = self
+            .downloader
+            .download_blocks(&blocks, storage, handle, |block, outcome| {
+                self.progress.send_modify(|progress| {
+                    for index in &files_by_block[&block.hash] {
+                        let file = &mut progress.files[*index];
+                        match outcome {
+                            BlockOutcome::Skipped | BlockOutcome::Fetched => {
+                                file.completed_blocks += 1;
+                                file.completed_bytes += block.length;
+                            }
+                            BlockOutcome::Failed => file.failed_blocks += 1,
+                        }
+                    }
+                    progress.overall = overall.borrow().clone();
+                });
+            })
+            .await?;
+        self.progress.send_modify(|progress| {
+            progress.overall = overall.borrow().clone();
+        });
+
+        let mut report = QueueReport {
+            blocks,
+            ..QueueReport::default()
+        };
+        for file in &self.progress.borrow().files {
+            if file.is_complete() {
+                report.complete_files.push(file.hash.clone());
+            } else {
+                report.incomplete_files.push(file.hash.clone());
+            }
+        }
+        Ok(report)
+    }
+}
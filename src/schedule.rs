@@ -0,0 +1,174 @@
+use std::{
+    collections::HashMap,
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+
+use crate::block::Block;
+use rand::Rng;
+
+/// A block of a file that still has to be requested. A block needed by several files of a queue
+/// is pending once, at its first position.
+#[derive(Debug, Clone)]
+pub struct PendingBlock {
+    /// Position of the file in the download, always 0 unless several files are downloaded together.
+    pub file: usize,
+    /// Position of the block in `File::blocks`.
+    pub index: usize,
+    /// Byte offset of the block in the file.
+    pub offset: u64,
+    pub block: Block,
+}
+
+impl PendingBlock {
+    pub fn range(&self) -> Range<u64> {
+        self.offset..self.offset + self.block.length
+    }
+}
+
+/// Decides in which order the missing blocks of a download are requested. The downloader asks
+/// again every time a request slot frees up, so a scheduler may change its mind during a download.
+pub trait Scheduler: Send + Sync {
+    /// Picks the next block to request as an index into `pending`, which is never empty. The order
+    /// of `pending` carries no meaning, it changes as blocks are taken out.
+    fn next(&self, pending: &[PendingBlock]) -> usize;
+}
+
+impl<S: Scheduler + ?Sized> Scheduler for Box<S> {
+    fn next(&self, pending: &[PendingBlock]) -> usize {
+        (**self).next(pending)
+    }
+}
+
+impl<S: Scheduler + ?Sized> Scheduler for Arc<S> {
+    fn next(&self, pending: &[PendingBlock]) -> usize {
+        (**self).next(pending)
+    }
+}
+
+/// Requests blocks from the start of the file to its end, which suits streaming playback. Files
+/// downloaded together are completed one after the other.
+pub struct SequentialScheduler;
+
+impl Scheduler for SequentialScheduler {
+    fn next(&self, pending: &[PendingBlock]) -> usize {
+        pending
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, pending)| (pending.file, pending.index))
+            .map(|(position, _)| position)
+            .unwrap_or(0)
+    }
+}
+
+/// Requests blocks in random order, which spreads the load of many clients across sources.
+pub struct RandomScheduler;
+
+impl Scheduler for RandomScheduler {
+    fn next(&self, pending: &[PendingBlock]) -> usize {
+        rand::thread_rng().gen_range(0..pending.len())
+    }
+}
+
+/// Requests the blocks that the fewest sources have first, so they are fetched while some source
+/// still offers them. Availability is reported by the caller, for example from the blocks peers
+/// announce, and blocks with unknown availability come last. Ties go in sequential order. Share it
+/// through an `Arc` to update the availability while a download is running.
+#[derive(Default)]
+pub struct RarestFirstScheduler {
+    sources: Mutex<HashMap<String, usize>>,
+}
+
+impl RarestFirstScheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Sets how many sources have the block with the given hash.
+    pub fn set_sources(&self, hash: &str, sources: usize) {
+        self.sources
+            .lock()
+            .unwrap()
+            .insert(hash.to_string(), sources);
+    }
+    pub fn clear(&self) {
+        self.sources.lock().unwrap().clear();
+    }
+}
+
+impl Scheduler for RarestFirstScheduler {
+    fn next(&self, pending: &[PendingBlock]) -> usize {
+        let sources = self.sources.lock().unwrap();
+        pending
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, pending)| {
+                let count = sources.get(&pending.block.hash).copied();
+                (count.unwrap_or(usize::MAX), pending.file, pending.index)
+            })
+            .map(|(position, _)| position)
+            .unwrap_or(0)
+    }
+}
+
+/// Requests the blocks overlapping caller supplied byte ranges first, the most recently added range
+/// before older ones, and falls back to another scheduler for the rest. Ranges are byte offsets
+/// within each file, so with several files the range applies to all of them, earlier files first. Share it through an `Arc`
+/// to add ranges while a download is running, for example when a player seeks.
+pub struct PriorityScheduler<S: Scheduler = SequentialScheduler> {
+    ranges: Mutex<Vec<Range<u64>>>,
+    fallback: S,
+}
+
+impl PriorityScheduler {
+    pub fn new() -> Self {
+        Self::with_fallback(SequentialScheduler)
+    }
+}
+
+impl Default for PriorityScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: Scheduler> PriorityScheduler<S> {
+    pub fn with_fallback(fallback: S) -> Self {
+        Self {
+            ranges: Mutex::new(Vec::new()),
+            fallback,
+        }
+    }
+    /// Makes the blocks overlapping the byte range the next ones to be requested.
+    pub fn prioritize(&self, range: Range<u64>) {
+        self.ranges.lock().unwrap().push(range);
+    }
+    pub fn clear(&self) {
+        self.ranges.lock().unwrap().clear();
+    }
+}
+
+impl<S: Scheduler> Scheduler for PriorityScheduler<S> {
+    fn next(&self, pending: &[PendingBlock]) -> usize {
+        let mut ranges = self.ranges.lock().unwrap();
+        while let Some(range) = ranges.last() {
+            let overlapping = pending
+                .iter()
+                .enumerate()
+                .filter(|(_, pending)| {
+                    let block = pending.range();
+                    block.start < range.end && range.start < block.end
+                })
+                .min_by_key(|(_, pending)| (pending.file, pending.offset))
+                .map(|(position, _)| position);
+            match overlapping {
+                Some(position) => return position,
+                // Everything in the range has been requested
+                None => {
+                    ranges.pop();
+                }
+            }
+        }
+        drop(ranges);
+        self.fallback.next(pending)
+    }
+}
@@ -7,7 +7,7 @@ use async_trait::async_trait;
 pub type BoxedStorage = Box<dyn Storage>;
 /// A storage is a collection of files addressed by their hashes, and block data. Files may be in any state of completeness or validity.
 #[async_trait]
-pub trait Storage {
+pub trait Storage: Send + Sync {
     async fn get_file(&self, hash: &str) -> Result<Option<File>>;
     async fn file_exists(&self, hash: &str) -> Result<bool>;
     async fn upsert_file(&mut self, file: &File) -> Result<()>;
@@ -34,10 +34,15 @@ impl MemoryStorage {
         }
     }
 }
+impl Default for MemoryStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 #[async_trait]
 impl Storage for MemoryStorage {
     async fn get_file(&self, hash: &str) -> Result<Option<File>> {
-        let file = self.files.get(hash).map(|f| f.clone());
+        let file = self.files.get(hash).cloned();
         Ok(file)
     }
 
@@ -56,7 +61,7 @@ impl Storage for MemoryStorage {
     }
 
     async fn get_block_data(&self, block: &Block) -> Result<Option<Vec<u8>>> {
-        let block = self.blocks.get(&block.hash).map(|b| b.clone());
+        let block = self.blocks.get(&block.hash).cloned();
         Ok(block)
     }
 
@@ -0,0 +1,171 @@
+mod common;
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use common::{source, Record, TestAcquirer};
+use futures::{stream::BoxStream, StreamExt};
+use incremental_file::{
+    acquirer::{
+        mirror::MirrorAcquirer, race::RacingAcquirer, storage::StorageAcquirer, AcquireError,
+        Acquirer,
+    },
+    block::Block,
+    downloader::{DownloadHandle, Downloader},
+    file::File,
+    storage::{MemoryStorage, Storage},
+};
+use tokio::sync::Notify;
+
+/// Answers whole batches at once, in reverse order, recording the size of every batch.
+fn batch_acquirer(storage: MemoryStorage, record: &Arc<Record>) -> TestAcquirer {
+    TestAcquirer::new(storage)
+        .with_reversed_batches()
+        .with_record(record.clone())
+}
+
+#[tokio::test]
+async fn downloads_in_batches() -> Result<()> {
+    let data = (0..100).collect::<Vec<u8>>();
+    let (file, storage) = source(&data, 10).await?;
+    let acquirer = batch_acquirer(storage, &Arc::default());
+    let downloader = Downloader::new(acquirer)
+        .with_batch_size(4)
+        .with_concurrency(2);
+    let mut storage = MemoryStorage::new();
+    let report = downloader.download(&file, &mut storage).await?;
+
+    assert!(report.is_complete());
+    assert_eq!(report.fetched.len(), 10);
+    assert_eq!(downloader.acquirer().record().batches(), vec![4, 4, 2]);
+    assert_eq!(file.data(&storage).await?, data);
+    Ok(())
+}
+#[tokio::test]
+async fn default_batch_requests_each_block() -> Result<()> {
+    let mut source = MemoryStorage::new();
+    let file = File::from_data((0..30).collect::<Vec<u8>>(), 10, &mut source).await?;
+    let mut blocks = file.blocks.clone();
+    blocks.push(Block::from_data([1, 2, 3]));
+    let acquirer = StorageAcquirer::new(source);
+    let results = acquirer.get_blocks(&blocks).collect::<Vec<_>>().await;
+
+    assert_eq!(results.len(), 4);
+    for (index, result) in &results[..3] {
+        blocks[*index].validate(result.as_ref().unwrap())?;
+    }
+    assert_eq!(results[3].0, 3);
+    assert!(results[3].1.is_err());
+    Ok(())
+}
+#[tokio::test]
+async fn mirrors_and_races_forward_batches() -> Result<()> {
+    let data = (0..100).collect::<Vec<u8>>();
+    let batches = Arc::default();
+    let mut acquirers = Vec::new();
+    for _ in 0..4 {
+        acquirers.push(batch_acquirer(source(&data, 10).await?.1, &batches).boxed());
+    }
+    let file = source(&data, 10).await?.0;
+    let race = RacingAcquirer::new(acquirers.split_off(2)).with_endgame_threshold(0);
+    let mirror = MirrorAcquirer::new(acquirers);
+
+    let downloader = Downloader::new(mirror).with_batch_size(4);
+    let mut storage = MemoryStorage::new();
+    assert!(downloader
+        .download(&file, &mut storage)
+        .await?
+        .is_complete());
+    assert_eq!(file.data(&storage).await?, data);
+    assert_eq!(downloader.acquirer().served(0), 10);
+    assert_eq!(batches.take_batches(), vec![4, 4, 2]);
+
+    let downloader = Downloader::new(race).with_batch_size(4);
+    let mut storage = MemoryStorage::new();
+    assert!(downloader
+        .download(&file, &mut storage)
+        .await?
+        .is_complete());
+    assert_eq!(file.data(&storage).await?, data);
+    assert_eq!(batches.batches(), vec![4, 4, 2]);
+    // Each batch is measured as one request
+    let race = downloader.acquirer();
+    assert_eq!(race.stats(0).requests + race.stats(1).requests, 3);
+    assert_eq!(race.stats(0).in_flight + race.stats(1).in_flight, 0);
+    Ok(())
+}
+#[tokio::test]
+async fn mirror_fails_over_blocks_missing_from_batch() -> Result<()> {
+    let data = (0..40).collect::<Vec<u8>>();
+    let (file, complete) = source(&data, 10).await?;
+    let (_, mut partial) = source(&data, 10).await?;
+    partial.remove_block_data(&file.blocks[1]).await?;
+    let batches = Arc::default();
+    let mirrors: Vec<Box<dyn Acquirer>> = vec![
+        batch_acquirer(partial, &batches).boxed(),
+        Box::new(StorageAcquirer::new(complete)),
+    ];
+    let acquirer = MirrorAcquirer::new(mirrors);
+    let results = acquirer.get_blocks(&file.blocks).collect::<Vec<_>>().await;
+
+    assert_eq!(results.len(), 4);
+    for (index, result) in results {
+        file.blocks[index].validate(&result?)?;
+    }
+    assert_eq!(batches.batches(), vec![4]);
+    assert_eq!((acquirer.served(0), acquirer.served(1)), (3, 1));
+    assert_eq!(acquirer.served_by(&file.blocks[0]), Some(0));
+    assert_eq!(acquirer.served_by(&file.blocks[1]), Some(1));
+    assert!(acquirer.is_healthy(0));
+    Ok(())
+}
+
+/// Sends the first block of every batch right away and the rest once released.
+struct HeldBatchAcquirer {
+    inner: TestAcquirer,
+    release: Notify,
+}
+
+#[async_trait]
+impl Acquirer for HeldBatchAcquirer {
+    async fn get_block(&self, _block: &Block) -> Result<Vec<u8>> {
+        Err(AcquireError::Rejected("Only batches are served".to_string()).into())
+    }
+    fn get_blocks<'a>(&'a self, blocks: &'a [Block]) -> BoxStream<'a, (usize, Result<Vec<u8>>)> {
+        futures::stream::iter(blocks.iter().enumerate())
+            .then(move |(index, block)| async move {
+                if index > 0 {
+                    self.release.notified().await;
+                }
+                (index, self.inner.get_block(block).await)
+            })
+            .boxed()
+    }
+}
+
+#[tokio::test]
+async fn blocks_of_a_batch_are_stored_as_they_arrive() -> Result<()> {
+    let (file, storage) = source(&(0..20).collect::<Vec<u8>>(), 10).await?;
+    let acquirer = HeldBatchAcquirer {
+        inner: TestAcquirer::new(storage),
+        release: Notify::new(),
+    };
+    let downloader = Downloader::new(acquirer).with_batch_size(2);
+    let handle = DownloadHandle::new();
+    let mut progress = handle.progress();
+    let mut storage = MemoryStorage::new();
+
+    let (report, _) = tokio::join!(
+        downloader.download_with_handle(&file, &mut storage, &handle),
+        async {
+            progress
+                .wait_for(|progress| progress.completed_blocks == 1)
+                .await
+                .unwrap();
+            downloader.acquirer().release.notify_one();
+        }
+    );
+    assert!(report?.is_complete());
+    Ok(())
+}
@@ -0,0 +1,243 @@
+#![allow(dead_code)]
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::{stream::BoxStream, StreamExt};
+use incremental_file::{
+    acquirer::{storage::StorageAcquirer, AcquireError, Acquirer},
+    block::Block,
+    file::File,
+    storage::{MemoryStorage, Storage},
+};
+
+/// A file of the data split into blocks of `block_size`, and a storage holding all of its blocks.
+pub async fn source(data: &[u8], block_size: u64) -> Result<(File, MemoryStorage)> {
+    let mut storage = MemoryStorage::new();
+    let file = File::from_data(data, block_size, &mut storage).await?;
+    Ok((file, storage))
+}
+
+/// How a `TestAcquirer` answers the requests it is told to fail.
+pub enum Failure {
+    Unavailable,
+    NotFound,
+    /// Serves the block with its first byte changed.
+    Corrupt,
+    /// Serves the block after a minute.
+    Hang,
+}
+
+/// What a `TestAcquirer` was asked for. It is shared, so tests can read it after the acquirer was
+/// moved into a downloader or another acquirer, and several acquirers can record into one.
+#[derive(Default)]
+pub struct Record {
+    requested: Mutex<Vec<String>>,
+    batches: Mutex<Vec<usize>>,
+    completed: AtomicUsize,
+    in_flight: AtomicUsize,
+    max_in_flight: AtomicUsize,
+}
+
+impl Record {
+    /// Hashes of the requested blocks, in the order the requests started. Blocks of a batch count
+    /// as one request each.
+    pub fn requested(&self) -> Vec<String> {
+        self.requested.lock().unwrap().clone()
+    }
+    pub fn attempts(&self) -> usize {
+        self.requested.lock().unwrap().len()
+    }
+    /// How many times each block was requested, by hash.
+    pub fn requests(&self) -> HashMap<String, usize> {
+        let mut requests = HashMap::new();
+        for hash in self.requested.lock().unwrap().iter() {
+            *requests.entry(hash.clone()).or_default() += 1;
+        }
+        requests
+    }
+    /// Sizes of the requests made, `get_block` counts as a batch of one.
+    pub fn batches(&self) -> Vec<usize> {
+        self.batches.lock().unwrap().clone()
+    }
+    pub fn take_batches(&self) -> Vec<usize> {
+        std::mem::take(&mut *self.batches.lock().unwrap())
+    }
+    /// Requests that ran to completion rather than being cancelled.
+    pub fn completed(&self) -> usize {
+        self.completed.load(Ordering::SeqCst)
+    }
+    pub fn max_in_flight(&self) -> usize {
+        self.max_in_flight.load(Ordering::SeqCst)
+    }
+}
+
+/// Serves blocks out of a memory storage through a `StorageAcquirer` and records every request.
+/// Blocks missing from the storage are not found. Delays, failures and the order in which batches
+/// are answered can be set up with the builder methods.
+pub struct TestAcquirer {
+    inner: StorageAcquirer<MemoryStorage>,
+    record: Arc<Record>,
+    delay: Box<dyn Fn(&Block) -> Duration + Send + Sync>,
+    failure: Option<(Failure, usize)>,
+    reverse_batches: bool,
+    on_request: Box<dyn Fn(&Block) + Send + Sync>,
+}
+
+impl TestAcquirer {
+    pub fn new(storage: MemoryStorage) -> Self {
+        Self {
+            inner: StorageAcquirer::new(storage),
+            record: Arc::default(),
+            delay: Box::new(|_| Duration::ZERO),
+            failure: None,
+            reverse_batches: false,
+            on_request: Box::new(|_| {}),
+        }
+    }
+    /// Waits before answering every request.
+    pub fn with_delay(self, delay: Duration) -> Self {
+        self.with_delay_by(move |_| delay)
+    }
+    /// Waits before answering each request for as long as the function says for its block.
+    pub fn with_delay_by<F: Fn(&Block) -> Duration + Send + Sync + 'static>(
+        mut self,
+        delay: F,
+    ) -> Self {
+        self.delay = Box::new(delay);
+        self
+    }
+    /// Fails the first `count` requests in the given way, then serves the blocks.
+    pub fn with_failures(mut self, failure: Failure, count: usize) -> Self {
+        self.failure = Some((failure, count));
+        self
+    }
+    /// Corrupts every block it serves.
+    pub fn corrupted(self) -> Self {
+        self.with_failures(Failure::Corrupt, usize::MAX)
+    }
+    /// Answers the blocks of every batch back to front.
+    pub fn with_reversed_batches(mut self) -> Self {
+        self.reverse_batches = true;
+        self
+    }
+    /// Records into a record shared with other acquirers.
+    pub fn with_record(mut self, record: Arc<Record>) -> Self {
+        self.record = record;
+        self
+    }
+    /// Calls the function with the block of every request as it starts.
+    pub fn on_request<F: Fn(&Block) + Send + Sync + 'static>(mut self, on_request: F) -> Self {
+        self.on_request = Box::new(on_request);
+        self
+    }
+    pub fn record(&self) -> Arc<Record> {
+        self.record.clone()
+    }
+    pub fn boxed(self) -> Box<dyn Acquirer> {
+        Box::new(self)
+    }
+
+    async fn serve(&self, block: &Block) -> Result<Vec<u8>> {
+        let attempt = {
+            let mut requested = self.record.requested.lock().unwrap();
+            requested.push(block.hash.clone());
+            requested.len()
+        };
+        (self.on_request)(block);
+        let in_flight = self.record.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.record
+            .max_in_flight
+            .fetch_max(in_flight, Ordering::SeqCst);
+        tokio::time::sleep((self.delay)(block)).await;
+        self.record.in_flight.fetch_sub(1, Ordering::SeqCst);
+        let result = match &self.failure {
+            Some((failure, count)) if attempt <= *count => match failure {
+                Failure::Unavailable => {
+                    Err(AcquireError::Unavailable("Server error".to_string()).into())
+                }
+                Failure::NotFound => Err(AcquireError::NotFound(block.hash.clone()).into()),
+                Failure::Corrupt => self.inner.get_block(block).await.map(|mut data| {
+                    if let Some(byte) = data.first_mut() {
+                        *byte = byte.wrapping_add(1);
+                    }
+                    data
+                }),
+                Failure::Hang => {
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                    self.inner.get_block(block).await
+                }
+            },
+            _ => self.inner.get_block(block).await,
+        };
+        self.record.completed.fetch_add(1, Ordering::SeqCst);
+        result
+    }
+}
+
+#[async_trait]
+impl Acquirer for TestAcquirer {
+    async fn get_block(&self, block: &Block) -> Result<Vec<u8>> {
+        self.record.batches.lock().unwrap().push(1);
+        self.serve(block).await
+    }
+    fn get_blocks<'a>(&'a self, blocks: &'a [Block]) -> BoxStream<'a, (usize, Result<Vec<u8>>)> {
+        self.record.batches.lock().unwrap().push(blocks.len());
+        let order = if self.reverse_batches {
+            (0..blocks.len()).rev().collect::<Vec<_>>()
+        } else {
+            (0..blocks.len()).collect()
+        };
+        futures::stream::iter(order)
+            .then(move |index| async move { (index, self.serve(&blocks[index]).await) })
+            .boxed()
+    }
+}
+
+/// Keeps blocks in memory and records the order they are written in.
+#[derive(Default)]
+pub struct RecordingStorage {
+    inner: MemoryStorage,
+    pub written: Vec<String>,
+}
+
+#[async_trait]
+impl Storage for RecordingStorage {
+    async fn get_file(&self, hash: &str) -> Result<Option<File>> {
+        self.inner.get_file(hash).await
+    }
+    async fn file_exists(&self, hash: &str) -> Result<bool> {
+        self.inner.file_exists(hash).await
+    }
+    async fn upsert_file(&mut self, file: &File) -> Result<()> {
+        self.inner.upsert_file(file).await
+    }
+    async fn remove_file(&mut self, hash: &str) -> Result<()> {
+        self.inner.remove_file(hash).await
+    }
+    async fn get_block_data(&self, block: &Block) -> Result<Option<Vec<u8>>> {
+        self.inner.get_block_data(block).await
+    }
+    async fn block_exists(&self, block: &Block) -> Result<bool> {
+        self.inner.block_exists(block).await
+    }
+    async fn upsert_block_data<D: AsRef<[u8]> + Send>(
+        &mut self,
+        block: &Block,
+        data: D,
+    ) -> Result<()> {
+        self.written.push(block.hash.clone());
+        self.inner.upsert_block_data(block, data).await
+    }
+    async fn remove_block_data(&mut self, block: &Block) -> Result<()> {
+        self.inner.remove_block_data(block).await
+    }
+}
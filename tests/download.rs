@@ -0,0 +1,231 @@
+mod common;
+
+use std::time::Duration;
+
+use anyhow::Result;
+use common::{RecordingStorage, TestAcquirer};
+use incremental_file::{
+    acquirer::Acquirer,
+    downloader::{DownloadHandle, DownloadState, Downloader},
+    file::File,
+    progress::ProgressEvent,
+    storage::{MemoryStorage, Storage},
+};
+
+/// A file of 10 blocks and an acquirer serving them, corrupted if asked to.
+async fn source(data: &[u8], corrupt: bool) -> Result<(File, TestAcquirer)> {
+    let (file, storage) = common::source(data, 10).await?;
+    let acquirer = TestAcquirer::new(storage);
+    Ok((
+        file,
+        if corrupt {
+            acquirer.corrupted()
+        } else {
+            acquirer
+        },
+    ))
+}
+
+/// Delays every request, so several of them are in flight at the same time.
+fn slow(acquirer: TestAcquirer) -> TestAcquirer {
+    acquirer.with_delay(Duration::from_millis(10))
+}
+
+#[tokio::test]
+async fn download_completes_file() -> Result<()> {
+    let data = (0..100).collect::<Vec<u8>>();
+    let (file, acquirer) = source(&data, false).await?;
+    let mut storage = MemoryStorage::new();
+    let report = Downloader::new(acquirer)
+        .download(&file, &mut storage)
+        .await?;
+
+    assert!(report.is_complete());
+    assert_eq!(report.fetched.len(), 10);
+    assert!(report.skipped.is_empty());
+    assert_eq!(file.data(&storage).await?, data);
+    Ok(())
+}
+#[tokio::test]
+async fn download_skips_existing_blocks() -> Result<()> {
+    let data = (0..100).collect::<Vec<u8>>();
+    let (file, acquirer) = source(&data, false).await?;
+    let mut storage = MemoryStorage::new();
+    for block in &file.blocks[..4] {
+        let block_data = acquirer.get_block(block).await?;
+        storage.upsert_block_data(block, block_data).await?;
+    }
+    let report = Downloader::new(acquirer)
+        .download(&file, &mut storage)
+        .await?;
+
+    assert_eq!(report.skipped.len(), 4);
+    assert_eq!(report.fetched.len(), 6);
+    file.validate(&storage).await?;
+    Ok(())
+}
+#[tokio::test]
+async fn download_rejects_invalid_blocks() -> Result<()> {
+    let data = (0..100).collect::<Vec<u8>>();
+    let (file, acquirer) = source(&data, true).await?;
+    let mut storage = MemoryStorage::new();
+    let report = Downloader::new(acquirer)
+        .download(&file, &mut storage)
+        .await?;
+
+    assert!(!report.is_complete());
+    assert_eq!(report.failed.len(), 10);
+    for block in &file.blocks {
+        assert!(!storage.block_exists(block).await?);
+    }
+    Ok(())
+}
+#[tokio::test]
+async fn download_limits_concurrent_requests() -> Result<()> {
+    let data = (0..200).collect::<Vec<u8>>();
+    let (file, inner) = source(&data, false).await?;
+    let acquirer = slow(inner);
+    let mut storage = MemoryStorage::new();
+    let downloader = Downloader::new(acquirer).with_concurrency(4);
+    let report = downloader.download(&file, &mut storage).await?;
+
+    assert!(report.is_complete());
+    assert_eq!(downloader.acquirer().record().max_in_flight(), 4);
+    let fetched = report.fetched.iter().map(|b| &b.hash).collect::<Vec<_>>();
+    let expected = file.blocks.iter().map(|b| &b.hash).collect::<Vec<_>>();
+    assert_eq!(fetched, expected);
+    assert_eq!(file.data(&storage).await?, data);
+    Ok(())
+}
+#[tokio::test]
+async fn blocks_are_written_in_request_order() -> Result<()> {
+    let data = (0..100).collect::<Vec<u8>>();
+    for batch_size in [1, 3] {
+        let (file, inner) = source(&data, false).await?;
+        let hashes = file
+            .blocks
+            .iter()
+            .map(|b| b.hash.clone())
+            .collect::<Vec<_>>();
+        // Blocks requested later are answered sooner, and every batch back to front
+        let positions = hashes.clone();
+        let acquirer = inner.with_reversed_batches().with_delay_by(move |block| {
+            let position = positions.iter().position(|hash| *hash == block.hash);
+            Duration::from_millis(5 * (10 - position.unwrap_or(0)) as u64)
+        });
+        let downloader = Downloader::new(acquirer)
+            .with_concurrency(4)
+            .with_batch_size(batch_size);
+        let mut storage = RecordingStorage::default();
+        let report = downloader.download(&file, &mut storage).await?;
+
+        assert!(report.is_complete());
+        assert_eq!(storage.written, hashes);
+        assert_eq!(file.data(&storage).await?, data);
+    }
+    Ok(())
+}
+#[tokio::test]
+async fn download_reports_progress() -> Result<()> {
+    let data = (0..100).collect::<Vec<u8>>();
+    let (file, acquirer) = source(&data, false).await?;
+    let mut storage = MemoryStorage::new();
+    for block in &file.blocks[..2] {
+        let block_data = acquirer.get_block(block).await?;
+        storage.upsert_block_data(block, block_data).await?;
+    }
+    let downloader = Downloader::new(acquirer);
+    let handle = DownloadHandle::new();
+    let mut progress = handle.progress();
+    let events = tokio::spawn(async move {
+        let mut received = Vec::new();
+        loop {
+            if let Some(ProgressEvent::BytesTransferred(block, bytes)) = &progress.borrow().event {
+                received.push((block.clone(), *bytes));
+            }
+            if progress.borrow().is_finished() || progress.changed().await.is_err() {
+                break;
+            }
+        }
+        (received, progress.borrow().clone())
+    });
+    downloader
+        .download_with_handle(&file, &mut storage, &handle)
+        .await?;
+
+    let progress = handle.progress().borrow().clone();
+    assert_eq!(progress.total_blocks, 10);
+    assert_eq!(progress.completed_blocks, 10);
+    assert_eq!(progress.completed_bytes, 100);
+    assert_eq!(progress.received_bytes, 80);
+    assert_eq!(progress.fraction(), 1.0);
+    assert_eq!(progress.eta, Some(std::time::Duration::ZERO));
+    assert!(progress.bytes_per_second > 0.0);
+    assert!(progress.is_finished());
+    // Receivers may skip events, but the totals they see account for all of them
+    let (received, seen) = events.await?;
+    assert!(received.iter().all(|(block, bytes)| *bytes == block.length));
+    assert!(matches!(seen.event, Some(ProgressEvent::BlockCompleted(_))));
+    assert_eq!(seen.completed_blocks, 10);
+    assert_eq!(seen.received_bytes, 80);
+
+    // Each download has its own progress, another one doesn't reset these totals
+    downloader.download(&file, &mut storage).await?;
+    assert_eq!(handle.progress().borrow().received_bytes, 80);
+    Ok(())
+}
+#[tokio::test]
+async fn download_can_pause_and_resume() -> Result<()> {
+    let data = (0..100).collect::<Vec<u8>>();
+    let (file, inner) = source(&data, false).await?;
+    let downloader = Downloader::new(slow(inner)).with_concurrency(2);
+    let handle = DownloadHandle::new();
+    let progress = handle.progress();
+    let mut storage = MemoryStorage::new();
+
+    let (report, _) = tokio::join!(
+        downloader.download_with_handle(&file, &mut storage, &handle),
+        async {
+            tokio::time::sleep(Duration::from_millis(25)).await;
+            handle.pause();
+            assert_eq!(handle.state(), DownloadState::Paused);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            let paused_at = progress.borrow().completed_blocks;
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert_eq!(progress.borrow().completed_blocks, paused_at);
+            assert!(paused_at < 10);
+            handle.resume();
+        }
+    );
+    assert!(report?.is_complete());
+    assert_eq!(file.data(&storage).await?, data);
+    Ok(())
+}
+#[tokio::test]
+async fn cancelled_download_resumes_with_missing_blocks() -> Result<()> {
+    let data = (0..100).collect::<Vec<u8>>();
+    let (file, inner) = source(&data, false).await?;
+    let downloader = Downloader::new(slow(inner)).with_concurrency(2);
+    let handle = DownloadHandle::new();
+    let mut storage = MemoryStorage::new();
+
+    let (report, _) = tokio::join!(
+        downloader.download_with_handle(&file, &mut storage, &handle),
+        async {
+            tokio::time::sleep(Duration::from_millis(25)).await;
+            handle.cancel();
+        }
+    );
+    let report = report?;
+    assert!(report.cancelled);
+    assert!(!report.is_complete());
+    let stored = report.fetched.len();
+    assert!(stored > 0 && stored < 10);
+
+    let report = downloader.download(&file, &mut storage).await?;
+    assert!(report.is_complete());
+    assert_eq!(report.skipped.len(), stored);
+    assert_eq!(report.fetched.len(), 10 - stored);
+    assert_eq!(file.data(&storage).await?, data);
+    Ok(())
+}
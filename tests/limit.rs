@@ -0,0 +1,163 @@
+mod common;
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Result;
+use common::{Failure, TestAcquirer};
+use incremental_file::{
+    acquirer::Acquirer,
+    block::Block,
+    downloader::Downloader,
+    file::File,
+    limit::{RateLimitedAcquirer, RateLimitedStorage, RateLimiter},
+    storage::{MemoryStorage, Storage},
+};
+use tokio::time::Instant;
+
+/// A file of 40 blocks, 100 bytes each.
+async fn source() -> Result<(File, TestAcquirer)> {
+    let data = (0..=255).cycle().take(4000).collect::<Vec<u8>>();
+    let (file, storage) = common::source(&data, 100).await?;
+    Ok((file, TestAcquirer::new(storage)))
+}
+
+#[tokio::test(start_paused = true)]
+async fn limits_combined_rate_of_concurrent_requests() -> Result<()> {
+    let (file, acquirer) = source().await?;
+    let limiter = RateLimiter::new(20_000);
+    let acquirer = RateLimitedAcquirer::new(acquirer, limiter);
+    let downloader = Downloader::new(acquirer).with_concurrency(8);
+    let mut storage = MemoryStorage::new();
+
+    let start = Instant::now();
+    let report = downloader.download(&file, &mut storage).await?;
+    let elapsed = start.elapsed();
+    assert!(report.is_complete());
+    // 4000 bytes at 20 000 bytes per second, minus a burst of at most 2000 bytes
+    assert!(elapsed >= Duration::from_millis(100), "{:?}", elapsed);
+    assert!(elapsed <= Duration::from_millis(200), "{:?}", elapsed);
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn rate_can_change_while_waiting() -> Result<()> {
+    let (file, acquirer) = source().await?;
+    let limiter = RateLimiter::new(100);
+    let acquirer = RateLimitedAcquirer::new(acquirer, limiter.clone());
+    let downloader = Downloader::new(acquirer).with_concurrency(8);
+    let mut storage = MemoryStorage::new();
+
+    let start = Instant::now();
+    let (report, _) = tokio::join!(downloader.download(&file, &mut storage), async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        limiter.set_rate(0);
+    });
+    assert!(report?.is_complete());
+    // 40 seconds at the original rate, but waiting callers pick up the change within 100ms
+    assert!(
+        start.elapsed() <= Duration::from_millis(150),
+        "{:?}",
+        start.elapsed()
+    );
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn limits_storage_writes() -> Result<()> {
+    let (file, acquirer) = source().await?;
+    let mut storage = RateLimitedStorage::new(MemoryStorage::new(), RateLimiter::new(20_000));
+
+    let start = Instant::now();
+    for block in &file.blocks[..20] {
+        let data = acquirer.get_block(block).await?;
+        storage.upsert_block_data(block, data).await?;
+    }
+    assert!(
+        start.elapsed() >= Duration::from_millis(80),
+        "{:?}",
+        start.elapsed()
+    );
+    assert!(
+        start.elapsed() <= Duration::from_millis(100),
+        "{:?}",
+        start.elapsed()
+    );
+    assert!(storage.block_exists(&file.blocks[19]).await?);
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn charges_received_bytes() -> Result<()> {
+    let (file, acquirer) = source().await?;
+    let acquirer = RateLimitedAcquirer::new(acquirer, RateLimiter::new(1000));
+    // Claims to be far larger than the 100 bytes that arrive
+    let block = Block::new(1_000_000, file.blocks[0].hash.clone());
+
+    let start = Instant::now();
+    for _ in 0..3 {
+        assert_eq!(acquirer.get_block(&block).await?.len(), 100);
+    }
+    // 300 bytes at 1000 bytes per second, rather than the 3MB the metadata claims
+    assert!(
+        start.elapsed() >= Duration::from_millis(150),
+        "{:?}",
+        start.elapsed()
+    );
+    assert!(
+        start.elapsed() <= Duration::from_millis(300),
+        "{:?}",
+        start.elapsed()
+    );
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn reserves_block_length_before_requesting() -> Result<()> {
+    let (file, acquirer) = source().await?;
+    let started = Arc::new(Mutex::new(Vec::new()));
+    let record = started.clone();
+    // Slow enough that every request would be in flight before the first one is charged
+    let acquirer = acquirer
+        .with_delay(Duration::from_millis(50))
+        .on_request(move |_| record.lock().unwrap().push(Instant::now()));
+    let acquirer = RateLimitedAcquirer::new(acquirer, RateLimiter::new(1000));
+
+    let start = Instant::now();
+    let requests = file.blocks[..4]
+        .iter()
+        .map(|block| acquirer.get_block(block));
+    for result in futures::future::join_all(requests).await {
+        result?;
+    }
+    // Each request waits for the 100 bytes reserved by the one before it to be paid off
+    let started = started.lock().unwrap().clone();
+    assert_eq!(started.len(), 4);
+    assert!(
+        started[3] - start >= Duration::from_millis(300),
+        "{:?}",
+        started[3] - start
+    );
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn failed_requests_release_their_reservation() -> Result<()> {
+    let (file, acquirer) = source().await?;
+    let acquirer = acquirer.with_failures(Failure::Unavailable, 3);
+    let acquirer = RateLimitedAcquirer::new(acquirer, RateLimiter::new(1000));
+
+    let start = Instant::now();
+    for _ in 0..3 {
+        assert!(acquirer.get_block(&file.blocks[0]).await.is_err());
+    }
+    acquirer.get_block(&file.blocks[0]).await?;
+    assert!(
+        start.elapsed() <= Duration::from_millis(10),
+        "{:?}",
+        start.elapsed()
+    );
+    Ok(())
+}
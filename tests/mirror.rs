@@ -0,0 +1,95 @@
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+use common::{Failure, Record, TestAcquirer};
+use incremental_file::{
+    acquirer::{mirror::MirrorAcquirer, AcquireError, Acquirer},
+    block::Block,
+    storage::{MemoryStorage, Storage},
+};
+
+enum Behavior {
+    Serve,
+    Fail,
+    Missing,
+}
+
+async fn mirror(behavior: Behavior) -> Result<(Box<dyn Acquirer>, Arc<Record>)> {
+    let mut storage = MemoryStorage::new();
+    if !matches!(behavior, Behavior::Missing) {
+        storage
+            .upsert_block_data(&block(), (0..10).collect::<Vec<u8>>())
+            .await?;
+    }
+    let mut mirror = TestAcquirer::new(storage);
+    if let Behavior::Fail = behavior {
+        mirror = mirror.with_failures(Failure::Unavailable, usize::MAX);
+    }
+    let record = mirror.record();
+    Ok((mirror.boxed(), record))
+}
+
+fn block() -> Block {
+    Block::from_data((0..10).collect::<Vec<u8>>())
+}
+
+#[tokio::test]
+async fn fails_over_to_next_mirror() -> Result<()> {
+    let (missing, _) = mirror(Behavior::Missing).await?;
+    let (failing, _) = mirror(Behavior::Fail).await?;
+    let (serving, _) = mirror(Behavior::Serve).await?;
+    let acquirer = MirrorAcquirer::new(vec![missing, failing, serving]);
+    let block = block();
+
+    assert_eq!(
+        acquirer.get_block(&block).await?,
+        (0..10).collect::<Vec<u8>>()
+    );
+    assert_eq!(acquirer.served_by(&block), Some(2));
+    assert_eq!(acquirer.served(2), 1);
+    assert_eq!(acquirer.served(0) + acquirer.served(1), 0);
+    assert!(acquirer.is_healthy(0));
+    Ok(())
+}
+#[tokio::test]
+async fn skips_unhealthy_mirror_until_cooldown_ends() -> Result<()> {
+    let (failing, failing_requests) = mirror(Behavior::Fail).await?;
+    let (serving, _) = mirror(Behavior::Serve).await?;
+    let acquirer = MirrorAcquirer::new(vec![failing, serving])
+        .with_failure_threshold(2)
+        .with_cooldown(Duration::from_millis(50));
+    let block = block();
+
+    for _ in 0..4 {
+        acquirer.get_block(&block).await?;
+    }
+    assert!(!acquirer.is_healthy(0));
+    assert_eq!(failing_requests.attempts(), 2);
+
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    assert!(acquirer.is_healthy(0));
+    acquirer.get_block(&block).await?;
+    assert_eq!(failing_requests.attempts(), 3);
+
+    // After its cooldown the mirror gets the whole threshold again
+    assert!(acquirer.is_healthy(0));
+    acquirer.get_block(&block).await?;
+    assert_eq!(failing_requests.attempts(), 4);
+    assert!(!acquirer.is_healthy(0));
+    assert_eq!(acquirer.served(1), 6);
+    Ok(())
+}
+#[tokio::test]
+async fn reports_not_found_when_no_mirror_has_block() -> Result<()> {
+    let (first, _) = mirror(Behavior::Missing).await?;
+    let (second, _) = mirror(Behavior::Missing).await?;
+    let acquirer = MirrorAcquirer::new(vec![first, second]);
+    let err = acquirer.get_block(&block()).await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<AcquireError>(),
+        Some(AcquireError::NotFound(_))
+    ));
+    Ok(())
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use common::TestAcquirer;
+use incremental_file::{
+    downloader::Downloader,
+    file::File,
+    queue::DownloadQueue,
+    schedule::PriorityScheduler,
+    storage::{MemoryStorage, Storage},
+};
+
+/// Serves blocks out of the storage after a short delay, so requests overlap.
+fn acquirer(storage: MemoryStorage) -> TestAcquirer {
+    TestAcquirer::new(storage).with_delay(Duration::from_millis(2))
+}
+
+#[tokio::test]
+async fn shared_blocks_are_fetched_once() -> Result<()> {
+    let mut source = MemoryStorage::new();
+    let common = (0..50).collect::<Vec<u8>>();
+    let mut files = Vec::new();
+    for extra in 0..3u8 {
+        let mut data = common.clone();
+        data.extend((0..20).map(|i| 100 + extra * 20 + i));
+        data.extend(&common);
+        files.push(File::from_data(&data, 10, &mut source).await?);
+    }
+    let mut queue = DownloadQueue::new(Downloader::new(acquirer(source)).with_concurrency(4));
+    for file in &files {
+        queue.push(file.clone());
+    }
+    let mut storage = MemoryStorage::new();
+    let report = queue.download(&mut storage).await?;
+
+    assert!(report.is_complete());
+    assert_eq!(report.complete_files.len(), 3);
+    // 5 common blocks and 2 blocks of filler per file
+    assert_eq!(report.blocks.fetched.len(), 5 + 3 * 2);
+    let requests = queue.downloader().acquirer().record().requests();
+    assert!(requests.values().all(|count| *count == 1));
+
+    let progress = queue.progress().borrow().clone();
+    for (file, file_progress) in files.iter().zip(&progress.files) {
+        assert_eq!(file_progress.hash, file.hash);
+        assert_eq!(file_progress.total_blocks, 7);
+        assert_eq!(file_progress.completed_bytes, 70);
+        assert_eq!(file_progress.fraction(), 1.0);
+        file.validate(&storage).await?;
+    }
+    assert_eq!(progress.overall.total_blocks, 11);
+    assert_eq!(progress.overall.completed_blocks, 11);
+    Ok(())
+}
+#[tokio::test]
+async fn reports_files_with_missing_blocks() -> Result<()> {
+    let mut source = MemoryStorage::new();
+    let first = File::from_data((0..30).collect::<Vec<u8>>(), 10, &mut source).await?;
+    let second = File::from_data((20..50).collect::<Vec<u8>>(), 10, &mut source).await?;
+    source.remove_block_data(&second.blocks[2]).await?;
+    let mut queue = DownloadQueue::new(Downloader::new(acquirer(source)));
+    queue.push(first.clone());
+    queue.push(second.clone());
+    let report = queue.download(&mut MemoryStorage::new()).await?;
+
+    assert!(!report.is_complete());
+    assert_eq!(report.complete_files, vec![first.hash]);
+    assert_eq!(report.incomplete_files, vec![second.hash]);
+    assert_eq!(queue.progress().borrow().files[1].failed_blocks, 1);
+    Ok(())
+}
+#[tokio::test]
+async fn schedulers_see_positions_within_each_file() -> Result<()> {
+    let mut source = MemoryStorage::new();
+    let first = File::from_data((0..30).collect::<Vec<u8>>(), 10, &mut source).await?;
+    let second = File::from_data((100..150).collect::<Vec<u8>>(), 10, &mut source).await?;
+    let scheduler = PriorityScheduler::new();
+    scheduler.prioritize(20..30);
+    let downloader = Downloader::new(acquirer(source)).with_scheduler(scheduler);
+    let mut queue = DownloadQueue::new(downloader);
+    queue.push(first.clone());
+    queue.push(second.clone());
+    let report = queue.download(&mut MemoryStorage::new()).await?;
+    assert!(report.is_complete());
+
+    // The range covers the third block of both files, then files are completed in order
+    let expected = [
+        &first.blocks[2],
+        &second.blocks[2],
+        &first.blocks[0],
+        &first.blocks[1],
+        &second.blocks[0],
+        &second.blocks[1],
+        &second.blocks[3],
+        &second.blocks[4],
+    ]
+    .map(|block| block.hash.clone());
+    let order = queue.downloader().acquirer().record().requested();
+    assert_eq!(order, expected);
+    Ok(())
+}
+#[tokio::test]
+async fn skipped_blocks_are_reported_with_the_new_totals() -> Result<()> {
+    let mut source = MemoryStorage::new();
+    let file = File::from_data((0..30).collect::<Vec<u8>>(), 10, &mut source).await?;
+    let mut storage = MemoryStorage::new();
+    for block in &file.blocks[..2] {
+        let data = source
+            .get_block_data(block)
+            .await?
+            .context("Block doesn't exist")?;
+        storage.upsert_block_data(block, data).await?;
+    }
+    let acquirer = TestAcquirer::new(source).with_delay(Duration::from_millis(100));
+    let mut queue = DownloadQueue::new(Downloader::new(acquirer));
+    queue.push(file.clone());
+    let mut progress = queue.progress();
+
+    let (report, overall) = tokio::join!(queue.download(&mut storage), async {
+        // Seen while the missing block is still being requested
+        let progress = progress
+            .wait_for(|progress| progress.files[0].completed_blocks == 2)
+            .await
+            .unwrap();
+        progress.overall.clone()
+    });
+    assert!(report?.is_complete());
+    assert_eq!(overall.total_blocks, 3);
+    assert_eq!(overall.completed_blocks, 2);
+    Ok(())
+}
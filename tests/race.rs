@@ -0,0 +1,95 @@
+mod common;
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use common::{Record, TestAcquirer};
+use incremental_file::{
+    acquirer::{race::RacingAcquirer, storage::StorageAcquirer, Acquirer},
+    downloader::Downloader,
+    file::File,
+    storage::MemoryStorage,
+};
+
+/// Sources serving the same file after the given delays, in milliseconds, with their records.
+async fn sources(delays: &[u64]) -> Result<(File, Vec<Arc<Record>>, RacingAcquirer)> {
+    let data = (0..=255).cycle().take(400).collect::<Vec<u8>>();
+    let file = common::source(&data, 10).await?.0;
+    let mut records = Vec::new();
+    let mut sources = Vec::new();
+    for delay in delays {
+        let storage = common::source(&data, 10).await?.1;
+        let source = TestAcquirer::new(storage).with_delay(Duration::from_millis(*delay));
+        records.push(source.record());
+        sources.push(source.boxed());
+    }
+    Ok((file, records, RacingAcquirer::new(sources)))
+}
+
+#[tokio::test]
+async fn sends_more_blocks_to_faster_sources() -> Result<()> {
+    let (file, records, acquirer) = sources(&[30, 2]).await?;
+    let downloader = Downloader::new(acquirer.with_endgame_threshold(0)).with_concurrency(4);
+    let mut storage = MemoryStorage::new();
+    let report = downloader.download(&file, &mut storage).await?;
+
+    assert!(report.is_complete());
+    let slow = records[0].completed();
+    let fast = records[1].completed();
+    assert!(fast > slow * 2, "fast: {}, slow: {}", fast, slow);
+    let stats = downloader.acquirer().stats(1);
+    assert!(stats.throughput.is_some());
+    assert_eq!(stats.in_flight, 0);
+    Ok(())
+}
+#[tokio::test]
+async fn endgame_races_sources_and_cancels_losers() -> Result<()> {
+    let (file, records, acquirer) = sources(&[1000, 5]).await?;
+    acquirer.set_remaining(1);
+    assert!(acquirer.is_endgame());
+
+    let start = Instant::now();
+    let block = &file.blocks[0];
+    acquirer.get_block(block).await?;
+    assert!(start.elapsed() < Duration::from_millis(500));
+    assert_eq!(acquirer.served_by(block), Some(1));
+    assert_eq!(records[0].completed(), 0);
+    assert_eq!(acquirer.stats(0).in_flight, 0);
+    Ok(())
+}
+#[tokio::test]
+async fn endgame_duplicates_requests_already_in_flight() -> Result<()> {
+    let (file, _, acquirer) = sources(&[1000, 5]).await?;
+    acquirer.set_remaining(10);
+    assert!(!acquirer.is_endgame());
+
+    let block = &file.blocks[0];
+    let start = Instant::now();
+    let (result, _) = tokio::join!(acquirer.get_block(block), async {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        acquirer.set_remaining(1);
+    });
+    result?;
+    assert!(start.elapsed() < Duration::from_millis(500));
+    assert_eq!(acquirer.served_by(block), Some(1));
+    Ok(())
+}
+#[tokio::test]
+async fn served_by_remembers_only_recent_blocks() -> Result<()> {
+    let mut source = MemoryStorage::new();
+    let data = (0..5000u32).flat_map(u32::to_le_bytes).collect::<Vec<u8>>();
+    let file = File::from_data(&data, 4, &mut source).await?;
+    let acquirer = RacingAcquirer::new(vec![Box::new(StorageAcquirer::new(source))]);
+    for block in &file.blocks {
+        acquirer.get_block(block).await?;
+    }
+
+    assert_eq!(acquirer.served_by(&file.blocks[0]), None);
+    assert_eq!(acquirer.served_by(&file.blocks[903]), None);
+    assert_eq!(acquirer.served_by(&file.blocks[904]), Some(0));
+    assert_eq!(acquirer.served_by(&file.blocks[4999]), Some(0));
+    Ok(())
+}
@@ -0,0 +1,178 @@
+mod common;
+
+use std::time::Duration;
+
+use anyhow::Result;
+use common::{Failure, TestAcquirer};
+use futures::StreamExt;
+use incremental_file::{
+    acquirer::{
+        retry::{RetryPolicy, RetryingAcquirer},
+        AcquireError, Acquirer,
+    },
+    block::Block,
+    storage::{MemoryStorage, Storage},
+};
+
+/// Fails the first `failures` requests for `block()` in the given way, then serves it.
+async fn flaky(failure: Failure, failures: usize) -> Result<TestAcquirer> {
+    let mut storage = MemoryStorage::new();
+    storage
+        .upsert_block_data(&block(), (0..10).collect::<Vec<u8>>())
+        .await?;
+    Ok(TestAcquirer::new(storage).with_failures(failure, failures))
+}
+
+fn policy() -> RetryPolicy {
+    RetryPolicy {
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(5),
+        ..RetryPolicy::default()
+    }
+}
+
+fn block() -> Block {
+    Block::from_data((0..10).collect::<Vec<u8>>())
+}
+
+#[tokio::test]
+async fn retries_temporary_failures() -> Result<()> {
+    let acquirer = RetryingAcquirer::new(flaky(Failure::Unavailable, 3).await?, policy())?;
+    let data = acquirer.get_block(&block()).await?;
+    assert_eq!(data, (0..10).collect::<Vec<u8>>());
+    assert_eq!(acquirer.inner().record().attempts(), 4);
+    Ok(())
+}
+#[tokio::test]
+async fn gives_up_after_max_attempts() -> Result<()> {
+    let acquirer = RetryingAcquirer::new(flaky(Failure::Unavailable, 10).await?, policy())?;
+    assert!(acquirer.get_block(&block()).await.is_err());
+    assert_eq!(acquirer.inner().record().attempts(), 5);
+    Ok(())
+}
+#[tokio::test]
+async fn does_not_retry_permanent_failures() -> Result<()> {
+    let acquirer = RetryingAcquirer::new(flaky(Failure::NotFound, 1).await?, policy())?;
+    let err = acquirer.get_block(&block()).await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<AcquireError>(),
+        Some(AcquireError::NotFound(_))
+    ));
+    assert_eq!(acquirer.inner().record().attempts(), 1);
+    Ok(())
+}
+#[tokio::test]
+async fn gives_up_on_repeated_hash_mismatch() -> Result<()> {
+    let acquirer = RetryingAcquirer::new(flaky(Failure::Corrupt, 1).await?, policy())?;
+    acquirer.get_block(&block()).await?;
+
+    let acquirer = RetryingAcquirer::new(flaky(Failure::Corrupt, 10).await?, policy())?;
+    assert!(acquirer.get_block(&block()).await.is_err());
+    assert_eq!(acquirer.inner().record().attempts(), 2);
+    Ok(())
+}
+#[tokio::test]
+async fn respects_timeouts() -> Result<()> {
+    let acquirer = RetryingAcquirer::new(
+        flaky(Failure::Hang, 1).await?,
+        RetryPolicy {
+            attempt_timeout: Some(Duration::from_millis(20)),
+            ..policy()
+        },
+    )?;
+    acquirer.get_block(&block()).await?;
+    assert_eq!(acquirer.inner().record().attempts(), 2);
+
+    let acquirer = RetryingAcquirer::new(
+        flaky(Failure::Hang, 10).await?,
+        RetryPolicy {
+            deadline: Some(Duration::from_millis(20)),
+            ..policy()
+        },
+    )?;
+    let err = acquirer.get_block(&block()).await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<AcquireError>(),
+        Some(AcquireError::Unavailable(_))
+    ));
+    assert_eq!(acquirer.inner().record().attempts(), 1);
+    Ok(())
+}
+#[test]
+fn policies_that_cant_compute_backoff_are_refused() {
+    let policies = [
+        RetryPolicy {
+            jitter: f64::NAN,
+            ..policy()
+        },
+        RetryPolicy {
+            jitter: -0.5,
+            ..policy()
+        },
+        RetryPolicy {
+            multiplier: -2.0,
+            ..policy()
+        },
+        RetryPolicy {
+            multiplier: f64::INFINITY,
+            ..policy()
+        },
+    ];
+    for policy in policies {
+        let acquirer = RetryingAcquirer::new(TestAcquirer::new(MemoryStorage::new()), policy);
+        assert!(acquirer.is_err());
+    }
+}
+#[tokio::test]
+async fn batches_are_limited_by_timeouts_and_retried_per_block() -> Result<()> {
+    let blocks = vec![block(); 3];
+    let acquirer = RetryingAcquirer::new(
+        flaky(Failure::Hang, 1).await?,
+        RetryPolicy {
+            attempt_timeout: Some(Duration::from_millis(20)),
+            ..policy()
+        },
+    )?;
+    let mut results = acquirer.get_blocks(&blocks).collect::<Vec<_>>().await;
+    results.sort_by_key(|(index, _)| *index);
+    assert_eq!(results.len(), 3);
+    for (index, (position, result)) in results.into_iter().enumerate() {
+        assert_eq!(position, index);
+        blocks[index].validate(&result?)?;
+    }
+    // The hanging batch, then one request per block
+    assert_eq!(acquirer.inner().record().attempts(), 4);
+
+    let acquirer = RetryingAcquirer::new(
+        flaky(Failure::Hang, 10).await?,
+        RetryPolicy {
+            deadline: Some(Duration::from_millis(20)),
+            ..policy()
+        },
+    )?;
+    let started = std::time::Instant::now();
+    let results = acquirer.get_blocks(&blocks).collect::<Vec<_>>().await;
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert_eq!(results.len(), 3);
+    for (_, result) in results {
+        let err = result.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<AcquireError>(),
+            Some(AcquireError::Unavailable(_))
+        ));
+    }
+    Ok(())
+}
+#[tokio::test]
+async fn batch_failures_are_retried() -> Result<()> {
+    let blocks = vec![block(); 2];
+    let acquirer = RetryingAcquirer::new(flaky(Failure::Corrupt, 1).await?, policy())?;
+    let results = acquirer.get_blocks(&blocks).collect::<Vec<_>>().await;
+    assert_eq!(results.len(), 2);
+    for (index, result) in results {
+        blocks[index].validate(&result?)?;
+    }
+    // The corrupted block is asked for again after the batch
+    assert_eq!(acquirer.inner().record().attempts(), 3);
+    Ok(())
+}
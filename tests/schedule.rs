@@ -0,0 +1,120 @@
+mod common;
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use common::TestAcquirer;
+use incremental_file::{
+    block::Block,
+    downloader::Downloader,
+    file::File,
+    schedule::{PendingBlock, PriorityScheduler, RandomScheduler, RarestFirstScheduler, Scheduler},
+    storage::MemoryStorage,
+};
+
+/// A file of 10 distinct blocks, 10 bytes each, and an acquirer calling `on_request` with the
+/// index of every block requested.
+async fn source<F: Fn(usize) + Send + Sync + 'static>(
+    on_request: F,
+) -> Result<(File, TestAcquirer)> {
+    let (file, storage) = common::source(&(0..100).collect::<Vec<u8>>(), 10).await?;
+    let blocks = file.blocks.clone();
+    let acquirer =
+        TestAcquirer::new(storage).on_request(move |block| on_request(index(&blocks, &block.hash)));
+    Ok((file, acquirer))
+}
+
+fn index(blocks: &[Block], hash: &str) -> usize {
+    blocks
+        .iter()
+        .position(|block| block.hash == hash)
+        .expect("Block is part of the file")
+}
+
+/// Indices of the blocks in the order they were requested.
+fn requested(file: &File, acquirer: &TestAcquirer) -> Vec<usize> {
+    acquirer
+        .record()
+        .requested()
+        .iter()
+        .map(|hash| index(&file.blocks, hash))
+        .collect()
+}
+
+#[tokio::test]
+async fn sequential_is_the_default() -> Result<()> {
+    let (file, acquirer) = source(|_| {}).await?;
+    let downloader = Downloader::new(acquirer);
+    downloader
+        .download(&file, &mut MemoryStorage::new())
+        .await?;
+    let requested = requested(&file, downloader.acquirer());
+    assert_eq!(requested, (0..10).collect::<Vec<_>>());
+    Ok(())
+}
+#[tokio::test]
+async fn random_requests_every_block_once() -> Result<()> {
+    let (file, acquirer) = source(|_| {}).await?;
+    let downloader = Downloader::new(acquirer).with_scheduler(RandomScheduler);
+    let mut storage = MemoryStorage::new();
+    let report = downloader.download(&file, &mut storage).await?;
+    assert!(report.is_complete());
+    let mut requested = requested(&file, downloader.acquirer());
+    requested.sort_unstable();
+    assert_eq!(requested, (0..10).collect::<Vec<_>>());
+    file.validate(&storage).await?;
+    Ok(())
+}
+#[tokio::test]
+async fn priority_ranges_are_requested_first() -> Result<()> {
+    let scheduler = Arc::new(PriorityScheduler::new());
+    scheduler.prioritize(75..85);
+    // Seeking again during the download takes precedence over the earlier range
+    let seek = scheduler.clone();
+    let (file, acquirer) = source(move |index| {
+        if index == 8 {
+            seek.prioritize(30..31);
+        }
+    })
+    .await?;
+    let downloader = Downloader::new(acquirer).with_scheduler(scheduler);
+    downloader
+        .download(&file, &mut MemoryStorage::new())
+        .await?;
+    let requested = requested(&file, downloader.acquirer());
+    assert_eq!(requested, vec![7, 8, 3, 0, 1, 2, 4, 5, 6, 9]);
+    Ok(())
+}
+#[tokio::test]
+async fn rarest_blocks_are_requested_first() -> Result<()> {
+    let (file, acquirer) = source(|_| {}).await?;
+    let scheduler = Arc::new(RarestFirstScheduler::new());
+    for (index, block) in file.blocks.iter().enumerate() {
+        if index % 3 != 0 {
+            scheduler.set_sources(&block.hash, 1 + index % 2);
+        }
+    }
+    let downloader = Downloader::new(acquirer).with_scheduler(scheduler);
+    downloader
+        .download(&file, &mut MemoryStorage::new())
+        .await?;
+    let requested = requested(&file, downloader.acquirer());
+    // One source, then two sources, then unknown availability, each in file order
+    assert_eq!(requested, vec![2, 4, 8, 1, 5, 7, 0, 3, 6, 9]);
+    Ok(())
+}
+#[tokio::test]
+async fn scheduler_picking_outside_pending_fails_download() -> Result<()> {
+    struct OutOfRange;
+    impl Scheduler for OutOfRange {
+        fn next(&self, pending: &[PendingBlock]) -> usize {
+            pending.len()
+        }
+    }
+    let (file, acquirer) = source(|_| {}).await?;
+    let downloader = Downloader::new(acquirer).with_scheduler(OutOfRange);
+    let result = downloader.download(&file, &mut MemoryStorage::new()).await;
+    assert!(result.is_err());
+    assert_eq!(downloader.acquirer().record().attempts(), 0);
+    Ok(())
+}
@@ -0,0 +1,35 @@
+use anyhow::Result;
+use incremental_file::{
+    acquirer::{storage::StorageAcquirer, AcquireError, Acquirer},
+    block::Block,
+    downloader::Downloader,
+    file::File,
+    storage::MemoryStorage,
+};
+
+#[tokio::test]
+async fn copies_file_between_storages() -> Result<()> {
+    let mut source = MemoryStorage::new();
+    let data = (0..100).collect::<Vec<u8>>();
+    let file = File::from_data(&data, 10, &mut source).await?;
+    let downloader = Downloader::new(StorageAcquirer::new(source));
+    let mut storage = MemoryStorage::new();
+    let report = downloader.download(&file, &mut storage).await?;
+
+    assert!(report.is_complete());
+    assert_eq!(file.data(&storage).await?, data);
+    Ok(())
+}
+#[tokio::test]
+async fn missing_block_is_not_found() -> Result<()> {
+    let acquirer = StorageAcquirer::new(MemoryStorage::new());
+    let err = acquirer
+        .get_block(&Block::from_data([1, 2, 3]))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<AcquireError>(),
+        Some(AcquireError::NotFound(_))
+    ));
+    Ok(())
+}
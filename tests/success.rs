@@ -1,11 +1,13 @@
 use anyhow::{Context, Result};
 use incremental_file::{
+    block::Block,
     crypto::{generate_keypair, get_public_key, parse_public_key},
     file::File,
     storage::{MemoryStorage, Storage},
 };
 
 #[test]
+#[allow(clippy::assertions_on_constants)]
 fn success() {
     assert!(true);
 }
@@ -74,8 +76,8 @@ async fn file_hash_is_correct() -> Result<()> {
     let file = File::from_data(&data, 10, &mut storage).await?;
     let hash = file.hash()?;
     let actual_hash = blake3::hash(&data);
-    assert!(format!("{}", hash).len() > 0);
-    assert!(format!("{}", actual_hash).len() > 0);
+    assert!(!format!("{}", hash).is_empty());
+    assert!(!format!("{}", actual_hash).is_empty());
     assert_eq!(format!("{}", hash), format!("{}", actual_hash));
     Ok(())
 }
@@ -103,10 +105,7 @@ async fn crypto_signature_fails() -> Result<()> {
     let keypair = generate_keypair()?;
     let public_key = get_public_key(&keypair)?;
     let public_key = parse_public_key(&public_key);
-    assert_eq!(
-        public_key.verify(&data, vec![0, 0, 0].as_slice()).is_err(),
-        true
-    );
+    assert!(public_key.verify(&data, vec![0, 0, 0].as_slice()).is_err());
     Ok(())
 }
 #[tokio::test]
@@ -131,3 +130,73 @@ async fn file_can_sign_and_verify() -> Result<()> {
     file.validate_and_verify(&storage, &public_key).await?;
     Ok(())
 }
+#[tokio::test]
+async fn file_signature_verifies_without_data() -> Result<()> {
+    let data = (0..100).collect::<Vec<u8>>();
+    let mut file = File::from_data(&data, 10, &mut MemoryStorage::new()).await?;
+    let keypair = generate_keypair()?;
+    let public_key = parse_public_key(&get_public_key(&keypair)?);
+    assert!(file.verify(&public_key).is_err());
+
+    file.sign(&keypair)?;
+    file.verify(&public_key)?;
+    let other_key = parse_public_key(&get_public_key(&generate_keypair()?)?);
+    assert!(file.verify(&other_key).is_err());
+    Ok(())
+}
+#[tokio::test]
+async fn file_signature_covers_blocks() -> Result<()> {
+    let data = (0..100).collect::<Vec<u8>>();
+    let mut file = File::from_data(&data, 10, &mut MemoryStorage::new()).await?;
+    let keypair = generate_keypair()?;
+    let public_key = parse_public_key(&get_public_key(&keypair)?);
+    file.sign(&keypair)?;
+
+    let mut swapped = file.clone();
+    swapped.blocks.swap(0, 1);
+    assert!(swapped.verify(&public_key).is_err());
+    let mut replaced = file.clone();
+    replaced.blocks[3] = Block::from_data([0; 10]);
+    assert!(replaced.verify(&public_key).is_err());
+    let mut resized = file.clone();
+    resized.blocks[3].length = 11;
+    assert!(resized.verify(&public_key).is_err());
+    let mut truncated = file.clone();
+    truncated.blocks.pop();
+    assert!(truncated.verify(&public_key).is_err());
+    file.verify(&public_key)?;
+    Ok(())
+}
+#[tokio::test]
+async fn file_progress_counts_stored_bytes() -> Result<()> {
+    let mut source = MemoryStorage::new();
+    let data = (0..105).collect::<Vec<u8>>();
+    let file = File::from_data(&data, 10, &mut source).await?;
+    let mut storage = MemoryStorage::new();
+    assert_eq!(file.unfinished_blocks(&storage).await?.len(), 11);
+    assert_eq!(file.progress(&storage).await?, 0.0);
+
+    for block in &file.blocks[..5] {
+        let block_data = source
+            .get_block_data(block)
+            .await?
+            .context("Block doesn't exist")?;
+        storage.upsert_block_data(block, block_data).await?;
+    }
+    assert_eq!(file.unfinished_blocks(&storage).await?.len(), 6);
+    assert_eq!(file.progress(&storage).await?, 50.0 / 105.0);
+    assert_eq!(file.progress(&source).await?, 1.0);
+    Ok(())
+}
+#[tokio::test]
+async fn file_signature_over_hash_only_is_rejected() -> Result<()> {
+    let data = (0..100).collect::<Vec<u8>>();
+    let mut file = File::from_data(&data, 10, &mut MemoryStorage::new()).await?;
+    let keypair = generate_keypair()?;
+    let public_key = parse_public_key(&get_public_key(&keypair)?);
+    // How earlier versions signed files
+    file.signature = Some(hex::encode(keypair.sign(file.hash()?.as_bytes())));
+
+    assert!(file.verify(&public_key).is_err());
+    Ok(())
+}